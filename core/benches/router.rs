@@ -0,0 +1,60 @@
+use core::handler::BoxCloneService;
+use core::route::Route;
+use core::tree::Tree;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Paths similar to ones of a typical REST API.
+fn paths() -> Vec<String> {
+    let mut paths = vec![];
+    for resource in [
+        "users", "repos", "orgs", "teams", "issues", "gists", "events",
+    ] {
+        paths.push(format!("/{}", resource));
+        paths.push(format!("/{}/<id>", resource));
+        paths.push(format!("/{}/<id>/members", resource));
+        paths.push(format!("/{}/<id>/members/<member>", resource));
+        paths.push(format!("/{}/<id>/settings", resource));
+        paths.push(format!("/{}/<id>/events/<event>/comments", resource));
+    }
+    paths
+}
+
+fn routes() -> Vec<Route> {
+    paths()
+        .into_iter()
+        .map(|path| Route::new(path, BoxCloneService::new(())).unwrap())
+        .collect()
+}
+
+fn bench_matchers(c: &mut Criterion) {
+    let routes = routes();
+    let mut tree = Tree::default();
+    for path in paths() {
+        tree.insert(&path, ()).unwrap();
+    }
+
+    let requests = [
+        "/users",
+        "/repos/42/members/john",
+        "/events/7/events/100/comments",
+        "/unknown/path",
+    ];
+
+    let mut group = c.benchmark_group("match");
+    for path in requests {
+        group.bench_function(format!("linear {}", path), |b| {
+            b.iter(|| {
+                routes
+                    .iter()
+                    .find(|route| route.should_fire_on_path(black_box(path)))
+            })
+        });
+        group.bench_function(format!("tree {}", path), |b| {
+            b.iter(|| tree.at(black_box(path)).map(|matched| matched.value))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_matchers);
+criterion_main!(benches);
@@ -1,8 +1,6 @@
-use anyhow::Context;
 use core::request::ContentType;
 use core::request::Json;
 use core::request::State;
-use core::response::Responder;
 use core::route::Router;
 use core::server::Server;
 use hyper::Body;
@@ -14,14 +12,6 @@ struct OwnBody {
     val: String,
 }
 
-impl Responder for OwnBody {
-    fn into_response(self) -> anyhow::Result<core::response::Response> {
-        serde_json::to_string(&self)
-            .context("could not serialize to string")?
-            .into_response()
-    }
-}
-
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
@@ -35,8 +25,8 @@ fn main() -> anyhow::Result<()> {
         Ok(body)
     }
 
-    fn handler5(Json(own_body): Json<OwnBody>) -> OwnBody {
-        own_body
+    fn handler5(Json(own_body): Json<OwnBody>) -> Json<OwnBody> {
+        Json(own_body)
     }
 
     fn handler_header(ContentType(content_type): ContentType) -> anyhow::Result<String> {
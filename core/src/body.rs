@@ -0,0 +1,176 @@
+use crate::{
+    middleware::Middleware,
+    request::{FromRequest, FromRequestAsync, Rejection},
+};
+use futures_util::Stream;
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::CONTENT_LENGTH,
+    Body, Request,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Default maximum size of request's body read by extractors,
+/// see `Router::body_limit`.
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Maximum size of request's body read by extractors, kept in request's
+/// extensions. Bodies over the limit are rejected with 413.
+///
+/// It's set by `Router::body_limit` for all of router's routes, as a middleware
+/// it overrides router's limit for the routes of `RouteGroup` or nested router.
+///
+/// ```rust
+/// use core::body::BodyLimit;
+/// use core::route::{RouteGroup, Router};
+///
+/// fn upload(body: hyper::body::Bytes) {}
+///
+/// let uploads = RouteGroup::new("/files")
+///     .middleware(BodyLimit(100 * 1024 * 1024))
+///     .post("/", upload);
+///
+/// let app = Router::default().body_limit(64 * 1024).groups(vec![uploads]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyLimit(pub usize);
+
+impl BodyLimit {
+    /// Limit that lets bodies of any size through.
+    pub fn unlimited() -> Self {
+        Self(usize::MAX)
+    }
+
+    /// Returns limit set for the request, `DEFAULT_BODY_LIMIT` when there is none.
+    pub fn of<B>(req: &Request<B>) -> usize {
+        req.extensions()
+            .get::<BodyLimit>()
+            .map_or(DEFAULT_BODY_LIMIT, |limit| limit.0)
+    }
+}
+
+impl Middleware for BodyLimit {
+    fn on_request(&self, req: &mut Request<Body>) -> anyhow::Result<()> {
+        req.extensions_mut().insert(*self);
+        Ok(())
+    }
+}
+
+/// Body read chunk by chunk, as it arrives, e.g. to store large upload
+/// without buffering it. Body is still limited, see `BodyLimit`, chunk
+/// that exceeds the limit is returned as 413 rejection.
+///
+/// Server passes body to the handler while it's being received, until handler
+/// returns. Body read after that, e.g. by a spawned task, fails.
+///
+/// Request which `Content-Length` is over the limit is rejected right away.
+///
+/// ```rust
+/// use core::body::BodyStream;
+/// use core::request::Rejection;
+///
+/// async fn upload(mut body: BodyStream) -> Result<String, Rejection> {
+///     let mut size = 0;
+///     while let Some(chunk) = body.chunk().await? {
+///         size += chunk.len();
+///     }
+///     Ok(size.to_string())
+/// }
+/// ```
+pub struct BodyStream {
+    body: Body,
+    limit: usize,
+
+    /// Number of bytes already read.
+    read: usize,
+}
+
+impl BodyStream {
+    /// Takes body of the request, fails when its `Content-Length` is over the limit.
+    pub(crate) fn new(req: Request<Body>) -> Result<Self, Rejection> {
+        let limit = BodyLimit::of(&req);
+
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some_and(|length| length > limit as u64) {
+            return Err(too_large(limit));
+        }
+
+        Ok(Self {
+            body: req.into_body(),
+            limit,
+            read: 0,
+        })
+    }
+
+    /// Returns next chunk of the body, `None` when whole body was read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Rejection> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
+    }
+
+    /// Reads rest of the body.
+    pub async fn bytes(mut self) -> Result<Bytes, Rejection> {
+        let mut data = vec![];
+        while let Some(chunk) = self.chunk().await? {
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.into())
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Bytes, Rejection>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunk = match Pin::new(&mut self.body).poll_data(cx) {
+            Poll::Ready(Some(Ok(chunk))) => chunk,
+            Poll::Ready(Some(Err(err))) => {
+                return Poll::Ready(Some(Err(Rejection::bad_request(format!(
+                    "could not read body: {}",
+                    err
+                )))))
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        self.read = self.read.saturating_add(chunk.len());
+        if self.read > self.limit {
+            return Poll::Ready(Some(Err(too_large(self.limit))));
+        }
+        Poll::Ready(Some(Ok(chunk)))
+    }
+}
+
+impl<S> FromRequest<Body, S> for BodyStream {
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        BodyStream::new(req)
+    }
+}
+
+impl<S> FromRequestAsync<Body, S> for BodyStream {
+    fn from_request_async(
+        req: Request<Body>,
+        _state: &S,
+    ) -> impl Future<Output = Result<Self, Rejection>> + Send {
+        std::future::ready(BodyStream::new(req))
+    }
+}
+
+/// Buffers whole body, respecting request's `BodyLimit`.
+pub(crate) async fn read_body(req: Request<Body>) -> Result<Bytes, Rejection> {
+    BodyStream::new(req)?.bytes().await
+}
+
+fn too_large(limit: usize) -> Rejection {
+    Rejection::payload_too_large(format!("body is larger than {} bytes", limit))
+}
@@ -0,0 +1,677 @@
+use crate::{
+    headers::{self, Header},
+    request::{FromRef, FromRequestParts, Rejection},
+    response::{Responder, Response, ResponseParts},
+};
+use anyhow::{anyhow, bail};
+use hyper::{
+    header::{HeaderValue, SET_COOKIE},
+    http::request::Parts,
+};
+use ring::{
+    aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+/// Cookie sent to client in `Set-Cookie` header, or received from it,
+/// then only its name and value are known.
+///
+/// Name has to be a token and value can't contain spaces, `"`, `,`, `;` nor `\`
+/// (RFC 6265 section 4.1.1), `Path` and `Domain` can't contain `;`. Response
+/// with cookie breaking these rules fails instead of sending it, so value taken
+/// from user can't inject attributes. Encode such value, e.g. with base64.
+///
+/// ```rust
+/// use core::cookies::{Cookie, SameSite};
+/// use std::time::Duration;
+///
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .http_only(true)
+///     .same_site(SameSite::Lax)
+///     .max_age(Duration::from_secs(3600));
+///
+/// assert_eq!(
+///     cookie.to_string(),
+///     "theme=dark; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+
+    /// Browsers accept it only for `Secure` cookies.
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => f.write_str("Strict"),
+            Self::Lax => f.write_str("Lax"),
+            Self::None => f.write_str("None"),
+        }
+    }
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Cookie without value, e.g. to remove it from `CookieJar`.
+    pub fn named(name: impl Into<String>) -> Self {
+        Self::new(name, "")
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Sets how long cookie lives, rounded down to seconds.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the same cookie with other name.
+    pub(crate) fn with_name(&self, name: String) -> Self {
+        Self {
+            name,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the same cookie with other value.
+    pub(crate) fn with_value(&self, value: String) -> Self {
+        Self {
+            value,
+            ..self.clone()
+        }
+    }
+
+    /// Returns cookie that makes client remove this one. Path and domain
+    /// have to match the ones cookie was set with.
+    pub(crate) fn removal(&self) -> Self {
+        Self {
+            value: String::new(),
+            max_age: Some(Duration::ZERO),
+            expires: Some(SystemTime::UNIX_EPOCH),
+            ..self.clone()
+        }
+    }
+
+    /// Formats cookie as value of `Set-Cookie` header, fails if any part
+    /// of it would be read by client as something else.
+    pub(crate) fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token_char) {
+            bail!("invalid cookie name {:?}", self.name);
+        }
+        let value = match self.value.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"').unwrap_or(&self.value),
+            None => &self.value,
+        };
+        if !value.bytes().all(is_cookie_octet) {
+            bail!("invalid value of cookie {}", self.name);
+        }
+        for (attribute, value) in [("Path", &self.path), ("Domain", &self.domain)] {
+            if let Some(value) = value {
+                if !value.bytes().all(is_attribute_octet) {
+                    bail!("invalid {} of cookie {}", attribute, self.name);
+                }
+            }
+        }
+
+        Ok(HeaderValue::from_str(&self.to_string())?)
+    }
+}
+
+/// `tchar` of RFC 7230, cookie's name is a token.
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+/// US-ASCII except controls, whitespace, `"`, `,`, `;` and `\`.
+fn is_cookie_octet(c: u8) -> bool {
+    matches!(c, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+/// Attribute's value can't contain controls nor `;` ending it.
+fn is_attribute_octet(c: u8) -> bool {
+    matches!(c, 0x20..=0x7E) && c != b';'
+}
+
+/// Formats cookie as value of `Set-Cookie` header.
+impl Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cookies of the request together with the ones added or removed by handler.
+/// Returned from handler, alone or as `(CookieJar, responder)`, it sends
+/// `Set-Cookie` header for every change.
+///
+/// ```rust
+/// use core::cookies::{Cookie, CookieJar};
+///
+/// fn visit(jar: CookieJar) -> (CookieJar, String) {
+///     let visits: u32 = jar
+///         .get("visits")
+///         .and_then(|cookie| cookie.value().parse().ok())
+///         .unwrap_or_default();
+///
+///     let jar = jar.insert(Cookie::new("visits", (visits + 1).to_string()).http_only(true));
+///     (jar, format!("visit number {}", visits + 1))
+/// }
+///
+/// fn forget(jar: CookieJar) -> CookieJar {
+///     jar.remove(Cookie::named("visits"))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    /// Current cookies, as handler sees them.
+    cookies: Vec<Cookie>,
+
+    /// Cookies to send in response, already encoded.
+    delta: Vec<Cookie>,
+}
+
+impl CookieJar {
+    fn from_headers(parts: &Parts) -> impl Iterator<Item = (String, String)> {
+        headers::Cookie::try_from_header_map(&parts.headers)
+            .unwrap_or_default()
+            .0
+            .into_iter()
+    }
+
+    /// Returns cookie with given name, including the ones added by handler.
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.iter().find(|cookie| cookie.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter()
+    }
+
+    /// Adds cookie, replacing the one with the same name.
+    pub fn insert(self, cookie: Cookie) -> Self {
+        let encoded = cookie.clone();
+        self.add_encoded(cookie, encoded)
+    }
+
+    /// Removes cookie, response makes client remove it as well.
+    pub fn remove(mut self, cookie: Cookie) -> Self {
+        self.cookies.retain(|c| c.name != cookie.name);
+        self.delta.retain(|c| c.name != cookie.name);
+        self.delta.push(cookie.removal());
+        self
+    }
+
+    fn add_encoded(mut self, cookie: Cookie, encoded: Cookie) -> Self {
+        self.cookies.retain(|c| c.name != cookie.name);
+        self.delta.retain(|c| c.name != cookie.name);
+        self.cookies.push(cookie);
+        self.delta.push(encoded);
+        self
+    }
+}
+
+/// Never fails, request without `Cookie` header gives empty jar.
+impl<S> FromRequestParts<S> for CookieJar {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        let cookies = Self::from_headers(parts)
+            .map(|(name, value)| Cookie::new(name, value))
+            .collect();
+
+        Ok(Self {
+            cookies,
+            delta: vec![],
+        })
+    }
+}
+
+impl ResponseParts for CookieJar {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        for cookie in self.delta {
+            let value = cookie.to_header_value()?;
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+        Ok(())
+    }
+}
+
+impl Responder for CookieJar {
+    fn into_response(self) -> anyhow::Result<Response> {
+        let mut response = Response::default();
+        self.extend_response(&mut response)?;
+        Ok(response)
+    }
+}
+
+/// Secret used to sign and encrypt cookies, taken from router's state.
+/// Cookies protected with other key are treated as missing.
+///
+/// ```rust
+/// use core::cookies::{Key, SignedCookieJar};
+/// use core::request::FromRef;
+/// use core::route::Router;
+///
+/// #[derive(Clone, FromRef)]
+/// struct AppState {
+///     key: Key,
+///     name: String,
+/// }
+///
+/// fn user(jar: SignedCookieJar) -> String {
+///     jar.get("user").map(|c| c.value().to_string()).unwrap_or_default()
+/// }
+///
+/// let state = AppState {
+///     key: Key::generate(),
+///     name: "app".into(),
+/// };
+/// let app = Router::with_state(state).get("/", user);
+/// ```
+#[derive(Clone)]
+pub struct Key([u8; Key::LEN]);
+
+impl Key {
+    /// Minimal length of master key.
+    pub const LEN: usize = 64;
+
+    /// Creates key from at least 64 bytes of master key, the first half of them
+    /// is used for signing, the second one for encryption.
+    pub fn new(master: &[u8]) -> anyhow::Result<Self> {
+        if master.len() < Self::LEN {
+            bail!(
+                "master key has {} bytes, it needs at least {}",
+                master.len(),
+                Self::LEN
+            );
+        }
+
+        let mut key = [0; Self::LEN];
+        key.copy_from_slice(&master[..Self::LEN]);
+        Ok(Self(key))
+    }
+
+    /// Generates random key. Cookies protected with it can't be read after restart.
+    pub fn generate() -> Self {
+        let mut key = [0; Self::LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .expect("system random generator failed");
+        Self(key)
+    }
+
+    fn signing(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, &self.0[..Self::LEN / 2])
+    }
+
+    fn encryption(&self) -> LessSafeKey {
+        let key = UnboundKey::new(&aead::AES_256_GCM, &self.0[Self::LEN / 2..])
+            .expect("AES-256 key has 32 bytes");
+        LessSafeKey::new(key)
+    }
+
+    /// Prepends value with base64 encoded signature of cookie's name and value.
+    fn sign(&self, name: &str, value: &str) -> String {
+        let tag = hmac::sign(&self.signing(), format!("{}={}", name, value).as_bytes());
+        format!("{}{}", base64::encode(tag.as_ref()), value)
+    }
+
+    fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        const SIGNATURE_LEN: usize = 44; // base64 of 32 bytes of SHA-256
+
+        if !signed.is_char_boundary(SIGNATURE_LEN) {
+            return None;
+        }
+        let (signature, value) = signed.split_at(SIGNATURE_LEN);
+        let signature = base64::decode(signature).ok()?;
+
+        hmac::verify(
+            &self.signing(),
+            format!("{}={}", name, value).as_bytes(),
+            &signature,
+        )
+        .ok()?;
+        Some(value.to_string())
+    }
+
+    /// Encrypts value with random nonce, cookie's name is authenticated along.
+    fn encrypt(&self, name: &str, value: &str) -> anyhow::Result<String> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("system random generator failed"))?;
+
+        let mut data = value.as_bytes().to_vec();
+        self.encryption()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(name.as_bytes()),
+                &mut data,
+            )
+            .map_err(|_| anyhow!("could not encrypt cookie {}", name))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(data);
+        Ok(base64::encode(sealed))
+    }
+
+    fn decrypt(&self, name: &str, sealed: &str) -> Option<String> {
+        let sealed = base64::decode(sealed).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, data) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut data = data.to_vec();
+
+        let value = self
+            .encryption()
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut data)
+            .ok()?;
+        String::from_utf8(value.to_vec()).ok()
+    }
+}
+
+/// `CookieJar` which cookies are signed with `Key` from router's state,
+/// so client can read them but can't change them. Cookies with invalid
+/// signature are dropped.
+pub struct SignedCookieJar {
+    jar: CookieJar,
+    key: Key,
+}
+
+impl SignedCookieJar {
+    /// Returns cookie with given name, value is already verified.
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.jar.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.jar.iter()
+    }
+
+    /// Adds cookie that is signed when it's sent.
+    pub fn insert(mut self, cookie: Cookie) -> Self {
+        let signed = cookie.with_value(self.key.sign(&cookie.name, &cookie.value));
+        self.jar = self.jar.add_encoded(cookie, signed);
+        self
+    }
+
+    pub fn remove(mut self, cookie: Cookie) -> Self {
+        self.jar = self.jar.remove(cookie);
+        self
+    }
+}
+
+impl<S> FromRequestParts<S> for SignedCookieJar
+where
+    Key: FromRef<S>,
+{
+    fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Rejection> {
+        let key = Key::from_ref(state);
+        let cookies = CookieJar::from_headers(parts)
+            .filter_map(|(name, value)| {
+                let value = key.verify(&name, &value)?;
+                Some(Cookie::new(name, value))
+            })
+            .collect();
+
+        Ok(Self {
+            jar: CookieJar {
+                cookies,
+                delta: vec![],
+            },
+            key,
+        })
+    }
+}
+
+impl ResponseParts for SignedCookieJar {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        self.jar.extend_response(response)
+    }
+}
+
+impl Responder for SignedCookieJar {
+    fn into_response(self) -> anyhow::Result<Response> {
+        self.jar.into_response()
+    }
+}
+
+/// `CookieJar` which cookies are encrypted with `Key` from router's state,
+/// so client can neither read nor change them. Cookies that can't be
+/// decrypted are dropped.
+pub struct PrivateCookieJar {
+    jar: CookieJar,
+    key: Key,
+}
+
+impl PrivateCookieJar {
+    /// Returns cookie with given name, value is already decrypted.
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.jar.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.jar.iter()
+    }
+
+    /// Adds cookie that is encrypted when it's sent.
+    pub fn insert(mut self, cookie: Cookie) -> anyhow::Result<Self> {
+        let encrypted = cookie.with_value(self.key.encrypt(&cookie.name, &cookie.value)?);
+        self.jar = self.jar.add_encoded(cookie, encrypted);
+        Ok(self)
+    }
+
+    pub fn remove(mut self, cookie: Cookie) -> Self {
+        self.jar = self.jar.remove(cookie);
+        self
+    }
+}
+
+impl<S> FromRequestParts<S> for PrivateCookieJar
+where
+    Key: FromRef<S>,
+{
+    fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Rejection> {
+        let key = Key::from_ref(state);
+        let cookies = CookieJar::from_headers(parts)
+            .filter_map(|(name, value)| {
+                let value = key.decrypt(&name, &value)?;
+                Some(Cookie::new(name, value))
+            })
+            .collect();
+
+        Ok(Self {
+            jar: CookieJar {
+                cookies,
+                delta: vec![],
+            },
+            key,
+        })
+    }
+}
+
+impl ResponseParts for PrivateCookieJar {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        self.jar.extend_response(response)
+    }
+}
+
+impl Responder for PrivateCookieJar {
+    fn into_response(self) -> anyhow::Result<Response> {
+        self.jar.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cookie, CookieJar, Key, SameSite};
+    use crate::response::Responder;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_set_cookie_format() {
+        let cookie = Cookie::new("id", "42")
+            .domain("example.com")
+            .secure(true)
+            .same_site(SameSite::None);
+        assert_eq!(
+            cookie.to_string(),
+            "id=42; Domain=example.com; Secure; SameSite=None"
+        );
+
+        let removal = Cookie::named("id")
+            .path("/app")
+            .expires(SystemTime::now() + Duration::from_secs(60))
+            .removal();
+        assert_eq!(
+            removal.to_string(),
+            "id=; Path=/app; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn test_invalid_cookies_are_not_sent() {
+        let injected = Cookie::new("id", "x; Domain=evil.com; Max-Age=99999");
+        assert!(injected.to_header_value().is_err());
+        let jar = CookieJar::default().insert(injected);
+        assert!(jar.into_response().is_err());
+
+        assert!(Cookie::new("id=1", "x").to_header_value().is_err());
+        assert!(Cookie::new("", "x").to_header_value().is_err());
+        assert!(Cookie::new("id", "a b").to_header_value().is_err());
+        assert!(Cookie::new("id", "\"a;b\"").to_header_value().is_err());
+        assert!(Cookie::new("id", "x")
+            .path("/; Secure")
+            .to_header_value()
+            .is_err());
+        assert!(Cookie::new("id", "x")
+            .domain("example.com\r\n")
+            .to_header_value()
+            .is_err());
+
+        let valid = Cookie::new("id", "\"a/b=c\"").path("/app dir");
+        assert_eq!(
+            valid.to_header_value().unwrap(),
+            "id=\"a/b=c\"; Path=/app dir"
+        );
+        assert!(Cookie::named("id").to_header_value().is_ok());
+    }
+
+    #[test]
+    fn test_signed_and_private_values() {
+        let key = Key::new(&[7; 64]).unwrap();
+        let other = Key::generate();
+
+        let signed = key.sign("user", "john");
+        assert!(signed.ends_with("john"));
+        assert_eq!(key.verify("user", &signed), Some("john".into()));
+        assert_eq!(key.verify("admin", &signed), None);
+        assert_eq!(other.verify("user", &signed), None);
+        assert_eq!(key.verify("user", &signed.replace("john", "jane")), None);
+        assert_eq!(key.verify("user", "john"), None);
+
+        let encrypted = key.encrypt("user", "john").unwrap();
+        assert!(!encrypted.contains("john"));
+        assert_ne!(encrypted, key.encrypt("user", "john").unwrap());
+        assert_eq!(key.decrypt("user", &encrypted), Some("john".into()));
+        assert_eq!(key.decrypt("admin", &encrypted), None);
+        assert_eq!(other.decrypt("user", &encrypted), None);
+        assert_eq!(key.decrypt("user", "AAAA"), None);
+
+        assert!(Key::new(&[0; 32]).is_err());
+    }
+}
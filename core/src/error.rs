@@ -0,0 +1,137 @@
+use crate::{
+    request::Rejection,
+    response::{Responder, Response},
+};
+use hyper::{header::CONTENT_TYPE, Body, StatusCode};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Error that is meant to be shown to the client. Handlers can return it directly,
+/// as `Err` of their `Result` or wrapped in `anyhow::Error`, e.g. with `?`.
+///
+/// It's sent as JSON: `{"error": message, "details": details}`,
+/// `details` are skipped when not set.
+///
+/// ```rust
+/// use core::error::HttpError;
+///
+/// fn handler() -> Result<String, HttpError> {
+///     Err(HttpError::not_found("user does not exist").with_details(["id", "name"]))
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    status: StatusCode,
+    message: String,
+    details: Option<serde_json::Value>,
+}
+
+impl HttpError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    /// Attaches additional information sent along with the message.
+    /// Details that can't be serialized to JSON are skipped.
+    pub fn with_details<T: Serialize>(mut self, details: T) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn details(&self) -> Option<&serde_json::Value> {
+        self.details.as_ref()
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a serde_json::Value>,
+}
+
+impl Responder for HttpError {
+    fn into_response(self) -> anyhow::Result<Response> {
+        let body = serde_json::to_vec(&ErrorBody {
+            error: &self.message,
+            details: self.details.as_ref(),
+        })?;
+
+        Ok(hyper::Response::builder()
+            .status(self.status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))?)
+    }
+}
+
+/// `HttpError` and `Rejection` keep their responses, other errors
+/// are unhandled ones and end up as 500, see `UnhandledError`.
+impl Responder for anyhow::Error {
+    fn into_response(self) -> anyhow::Result<Response> {
+        let err = match self.downcast::<HttpError>() {
+            Ok(err) => return err.into_response(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<Rejection>() {
+            Ok(rejection) => return rejection.into_response(),
+            Err(err) => err,
+        };
+
+        Ok(internal_error(err))
+    }
+}
+
+/// Error that was not turned into a response by the application. Response built
+/// for it carries the error in its extensions, so `Router::error_handler`
+/// can render it once more and log it.
+#[derive(Debug)]
+pub struct UnhandledError(pub anyhow::Error);
+
+/// Builds 500 response with error's message as a body and remembers the error.
+pub(crate) fn internal_error(err: anyhow::Error) -> Response {
+    let mut response = Response::new(Body::from(err.to_string()));
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response.extensions_mut().insert(UnhandledError(err));
+    response
+}
@@ -1,15 +1,44 @@
 use crate::{
-    request::{FromRequest, FromRequestParts},
-    response::{Responder, Response},
+    error::internal_error,
+    request::{FromRequest, FromRequestAsync, FromRequestParts},
+    response::{AsyncResponder, Responder, Response},
 };
 use hyper::{Body, Request};
-use std::{marker::PhantomData, sync::Arc};
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Arc};
+
+mod private {
+    use std::marker::PhantomData;
+
+    /// Wraps parameters' types of `async fn` handlers, so they don't
+    /// overlap with synchronous ones.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ViaAsync<Q>(PhantomData<Q>);
+
+    /// Marks services registered as handlers.
+    #[derive(Debug, Clone, Copy)]
+    pub enum ViaService {}
+}
+
+/// Boxed future returned by asynchronous counterparts of services and middlewares.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
 /// Trait implemented by transition handler's state.
 /// Introduced to have handlers that are generic only over R type.
 pub trait Service<R> {
     /// Calls service's logic.
     fn call(&self, req: R) -> Response;
+
+    /// Calls service without blocking the thread if `is_blocking` is false for
+    /// the request. By default `call` is run before the future is returned.
+    fn call_async<'a>(&'a self, req: R) -> BoxFuture<'a, Response> {
+        Box::pin(std::future::ready(self.call(req)))
+    }
+
+    /// Tells if calling service with the request can block the thread. Async server
+    /// awaits non-blocking services on the runtime, the rest runs on tokio's blocking pool.
+    fn is_blocking(&self, _req: &R) -> bool {
+        true
+    }
 }
 
 /// Transition state for handler, it helps 'hide' Q type that is specific
@@ -30,6 +59,14 @@ where
     fn call(&self, req: Request<Body>) -> Response {
         self.handler.handle(req, &self.state.clone())
     }
+
+    fn call_async<'a>(&'a self, req: Request<Body>) -> BoxFuture<'a, Response> {
+        self.handler.handle_async(req, &self.state)
+    }
+
+    fn is_blocking(&self, req: &Request<Body>) -> bool {
+        self.handler.is_blocking(req)
+    }
 }
 
 impl<B> Service<Request<B>> for () {
@@ -47,6 +84,17 @@ pub trait HandlerTrait<Q, S = ()>: Sized + Send + Sync + 'static {
     /// User defined logic.
     fn handle(&self, request: Request<Body>, state: &S) -> Response;
 
+    /// Runs handler without blocking the thread, `async fn` handlers return
+    /// their future. Other handlers are run by `handle` before it's returned.
+    fn handle_async<'a>(&'a self, request: Request<Body>, state: &'a S) -> BoxFuture<'a, Response> {
+        Box::pin(std::future::ready(self.handle(request, state)))
+    }
+
+    /// Tells if `handle` can block the thread, false for `async fn` handlers.
+    fn is_blocking(&self, _request: &Request<Body>) -> bool {
+        true
+    }
+
     /// Turns Self into `IntoService`.
     fn into_service_with_state(self, state: S) -> IntoService<Self, S, Q> {
         IntoService {
@@ -87,16 +135,21 @@ macro_rules! implement_handler_trait {
             fn handle(&self, request: Request<Body>, state: &S) -> Response {
                 let (mut parts, body) = request.into_parts();
 
-                match self(
-                    $(
-                        $ty::from_request_parts(&mut parts, state).unwrap(),
-                    )*
-                    $last::from_request(Request::from_parts(parts, body), state).unwrap(),
-                )
-                .into_response()
+                $(
+                    let $ty = match $ty::from_request_parts(&mut parts, state) {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into(),
+                    };
+                )*
+                let $last = match $last::from_request(Request::from_parts(parts, body), state) {
+                    Ok(value) => value,
+                    Err(rejection) => return rejection.into(),
+                };
+
+                match self($($ty,)* $last).into_response()
                 {
                     Ok(response) => response,
-                    Err(_e) => Response::default(),
+                    Err(e) => internal_error(e),
                 }
             }
         }
@@ -115,19 +168,157 @@ where
     F: Fn() -> R + Send + Sync + 'static,
 {
     fn handle(&self, _request: Request<Body>, _state: &S) -> Response {
-        match self().into_response() {
-            Ok(response) => response,
-            Err(_e) => Response::default(),
+        self().into_response().unwrap_or_else(internal_error)
+    }
+}
+
+/// Implements HandlerTrait for `async fn` handlers. Parameters are created
+/// with `FromRequestAsync`, so reading the body does not block the thread.
+///
+/// Async server (`tokio` feature) awaits handler's future on the runtime,
+/// unless a blocking middleware wraps it. Then, like the synchronous server,
+/// it drives the future to completion on the calling thread, with runtime's
+/// handle when there's one so tokio's IO can be used.
+///
+/// ```rust
+/// use core::route::Router;
+///
+/// async fn handler(body: String) -> String {
+///     body
+/// }
+///
+/// Router::default().post("/", handler);
+/// ```
+macro_rules! implement_async_handler_trait {
+    ([$($ty:ident),*], $last:ident) => {
+        #[allow(non_snake_case, unused_mut)]
+        impl<F, Fut, S, R, $($ty,)* $last, M> HandlerTrait<private::ViaAsync<($($ty,)* $last, M)>, S> for F
+        where
+            R: AsyncResponder + 'static,
+            Fut: Future<Output = R> + Send,
+            S: Sync,
+            $($ty:FromRequestParts<S> + Send,)*
+            $last: FromRequestAsync<Body, S, M>,
+            F: Fn($($ty,)* $last) -> Fut + Send + Sync + 'static
+        {
+            fn handle(&self, request: Request<Body>, state: &S) -> Response {
+                block_on(self.handle_async(request, state))
+            }
+
+            fn handle_async<'a>(&'a self, request: Request<Body>, state: &'a S) -> BoxFuture<'a, Response> {
+                Box::pin(async move {
+                    let (mut parts, body) = request.into_parts();
+
+                    $(
+                        let $ty = match $ty::from_request_parts(&mut parts, state) {
+                            Ok(value) => value,
+                            Err(rejection) => return rejection.into(),
+                        };
+                    )*
+                    let request = Request::from_parts(parts, body);
+                    let $last = match $last::from_request_async(request, state).await {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into(),
+                    };
+
+                    self($($ty,)* $last)
+                    .await
+                    .into_response_async()
+                    .await
+                    .unwrap_or_else(internal_error)
+                })
+            }
+
+            fn is_blocking(&self, _request: &Request<Body>) -> bool {
+                false
+            }
         }
+    };
+}
+
+implement_async_handler_trait!([], T1);
+implement_async_handler_trait!([T1], T2);
+implement_async_handler_trait!([T1, T2], T3);
+implement_async_handler_trait!([T1, T2, T3], T4);
+implement_async_handler_trait!([T1, T2, T3, T4], T5);
+
+impl<F, Fut, S, R> HandlerTrait<private::ViaAsync<()>, S> for F
+where
+    R: AsyncResponder + 'static,
+    Fut: Future<Output = R> + Send,
+    F: Fn() -> Fut + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request<Body>, state: &S) -> Response {
+        block_on(self.handle_async(request, state))
+    }
+
+    fn handle_async<'a>(
+        &'a self,
+        _request: Request<Body>,
+        _state: &'a S,
+    ) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            self()
+                .await
+                .into_response_async()
+                .await
+                .unwrap_or_else(internal_error)
+        })
+    }
+
+    fn is_blocking(&self, _request: &Request<Body>) -> bool {
+        false
     }
 }
 
+/// Drives future to completion on the current thread. Inside of multi-threaded
+/// tokio runtime its handle is used, so futures can rely on tokio's IO and timers.
+/// Runtime's worker hands its other tasks over to other workers before blocking,
+/// so synchronous services can be called from async handlers as well.
+pub(crate) fn block_on<F>(future: F) -> F::Output
+where
+    F: Future,
+{
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        // Current thread runtime can't block in place, its worker would panic.
+        if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread {
+            return tokio::task::block_in_place(|| handle.block_on(future));
+        }
+    }
+
+    futures_executor::block_on(future)
+}
+
 impl<S> HandlerTrait<(), S> for () {
     fn handle(&self, _request: Request<Body>, _state: &S) -> Response {
         Response::default()
     }
 }
 
+/// Services, e.g. handlers already turned into `IntoService`, can be registered
+/// wherever handlers are expected. State given by the router is not passed to them.
+impl<V, S> HandlerTrait<private::ViaService, S> for V
+where
+    V: Service<Request<Body>> + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request<Body>, _state: &S) -> Response {
+        self.call(request)
+    }
+
+    fn handle_async<'a>(
+        &'a self,
+        request: Request<Body>,
+        _state: &'a S,
+    ) -> BoxFuture<'a, Response> {
+        self.call_async(request)
+    }
+
+    fn is_blocking(&self, request: &Request<Body>) -> bool {
+        Service::is_blocking(self, request)
+    }
+}
+
 pub struct BoxCloneService<T>(pub Box<dyn Service<T> + Send + Sync>);
 
 impl<T> BoxCloneService<T> {
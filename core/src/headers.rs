@@ -0,0 +1,760 @@
+use crate::{
+    request::{FromRequestParts, Rejection},
+    response::{Responder, Response, ResponseParts},
+};
+use anyhow::{anyhow, bail};
+use hyper::{
+    header::{
+        HeaderName, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST, IF_MODIFIED_SINCE,
+        IF_NONE_MATCH, ORIGIN, RANGE, USER_AGENT,
+    },
+    http::{request::Parts, HeaderValue},
+    HeaderMap,
+};
+use std::{fmt::Display, time::SystemTime};
+
+/// Trait is implemented for types that can be turned from HeaderMap by specific key
+/// and back into header's value.
+///
+/// Headers implementing this trait can be used straight as handler's params,
+/// or wrapped with `TypedHeader`, which also sends them in responses.
+///
+/// ```rust
+/// use core::headers::ContentType;
+///
+/// fn handler_header(ContentType(content_type): ContentType) -> anyhow::Result<String> {
+///     Ok(content_type)
+/// }
+/// ```
+pub trait Header: Sized {
+    /// Returns header's key.
+    fn key() -> HeaderName;
+
+    /// Tries to create Self from HeaderValue.
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self>;
+
+    /// Turns Self into HeaderValue, e.g. to send it in response.
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue>;
+
+    /// Default implementation that uses `key` and `try_from_header_value` functions
+    /// to turn `map: HeaderMap<HeaderValue>` into `Result<Self, Rejection>`.
+    /// Missing or invalid header rejects request with 400.
+    fn try_from_header_map(map: &HeaderMap<HeaderValue>) -> Result<Self, Rejection> {
+        let key = Self::key();
+        let value = map
+            .get(&key)
+            .ok_or_else(|| Rejection::bad_request(format!("missing header {}", key)))?;
+
+        Self::try_from_header_value(value)
+            .map_err(|e| Rejection::bad_request(format!("invalid header {}: {}", key, e)))
+    }
+}
+
+/// Header as an extractor or a responder. Missing or invalid header rejects
+/// request with 400, `Option<TypedHeader<H>>` gives `None` instead.
+/// Returned from handler, it's sent in otherwise empty response or, as
+/// `(TypedHeader<H>, responder)`, in responder's response.
+///
+/// ```rust
+/// use core::headers::{Authorization, TypedHeader, UserAgent};
+///
+/// fn handler(
+///     TypedHeader(auth): TypedHeader<Authorization>,
+///     user_agent: Option<TypedHeader<UserAgent>>,
+/// ) -> String {
+///     match auth {
+///         Authorization::Bearer(token) => token,
+///         Authorization::Basic { username, .. } => username,
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedHeader<H>(pub H);
+
+impl<S, H> FromRequestParts<S> for TypedHeader<H>
+where
+    H: Header,
+{
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        H::try_from_header_map(&parts.headers).map(TypedHeader)
+    }
+}
+
+impl<H> ResponseParts for TypedHeader<H>
+where
+    H: Header,
+{
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        response
+            .headers_mut()
+            .insert(H::key(), self.0.to_header_value()?);
+        Ok(())
+    }
+}
+
+impl<H> Responder for TypedHeader<H>
+where
+    H: Header,
+{
+    fn into_response(self) -> anyhow::Result<Response> {
+        let mut response = Response::default();
+        self.extend_response(&mut response)?;
+        Ok(response)
+    }
+}
+
+/// Joins all values of the header, for headers that can be sent multiple times.
+fn try_from_joined_values<H: Header>(
+    map: &HeaderMap<HeaderValue>,
+    separator: &str,
+) -> Result<H, Rejection> {
+    let key = H::key();
+    let values = map
+        .get_all(&key)
+        .iter()
+        .map(|value| value.to_str())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Rejection::bad_request(format!("invalid header {}: {}", key, e)))?;
+    if values.is_empty() {
+        return Err(Rejection::bad_request(format!("missing header {}", key)));
+    }
+
+    HeaderValue::from_str(&values.join(separator))
+        .map_err(anyhow::Error::from)
+        .and_then(|value| H::try_from_header_value(&value))
+        .map_err(|e| Rejection::bad_request(format!("invalid header {}: {}", key, e)))
+}
+
+/// Macro for faster Header implementations of headers kept as raw strings.
+macro_rules! derive_header {
+    ($type:ident(_), name: $name:ident) => {
+        impl Header for $type {
+            fn key() -> HeaderName {
+                $name
+            }
+
+            fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+                Ok($type(header_value.to_str()?.to_string()))
+            }
+
+            fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+                Ok(HeaderValue::from_str(&self.0)?)
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType(pub String);
+derive_header!(ContentType(_), name: CONTENT_TYPE);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host(pub String);
+derive_header!(Host(_), name: HOST);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAgent(pub String);
+derive_header!(UserAgent(_), name: USER_AGENT);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLength(pub u64);
+
+impl Header for ContentLength {
+    fn key() -> HeaderName {
+        CONTENT_LENGTH
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        Ok(Self(header_value.to_str()?.trim().parse()?))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        Ok(self.0.into())
+    }
+}
+
+/// Credentials of `Bearer` or `Basic` authentication scheme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    Bearer(String),
+    Basic { username: String, password: String },
+}
+
+impl Header for Authorization {
+    fn key() -> HeaderName {
+        AUTHORIZATION
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let value = header_value.to_str()?;
+        let (scheme, credentials) = value
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("missing credentials"))?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if credentials.is_empty() {
+                bail!("empty bearer token");
+            }
+            return Ok(Self::Bearer(credentials.to_string()));
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(base64::decode(credentials)?)?;
+            let (username, password) = decoded
+                .split_once(':')
+                .ok_or_else(|| anyhow!("basic credentials without ':'"))?;
+            return Ok(Self::Basic {
+                username: username.to_string(),
+                password: password.to_string(),
+            });
+        }
+
+        bail!("unsupported authorization scheme {}", scheme)
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        let value = match self {
+            Self::Bearer(token) => format!("Bearer {}", token),
+            Self::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    base64::encode(format!("{}:{}", username, password))
+                )
+            }
+        };
+        Ok(HeaderValue::from_str(&value)?)
+    }
+}
+
+/// Media types client accepts, ordered from the most preferred one.
+/// Parameters of media ranges other than `q` are dropped.
+///
+/// ```rust
+/// use core::headers::{Accept, TypedHeader};
+///
+/// fn handler(accept: Option<TypedHeader<Accept>>) -> &'static str {
+///     let available = ["application/json", "text/html"];
+///     match accept.and_then(|TypedHeader(accept)| accept.preferred(&available)) {
+///         Some("text/html") => "<p>hello</p>",
+///         _ => "{\"hello\": true}",
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept(pub Vec<MediaRange>);
+
+/// Single media range of `Accept` header, e.g. `text/*;q=0.8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// Lowercase `type/subtype`, any of them can be `*`.
+    pub mime: String,
+
+    /// Weight between 0 and 1, 1 when not given.
+    pub quality: f32,
+}
+
+impl MediaRange {
+    /// Returns how specific the range is for the media type, `None` when it doesn't match.
+    fn precedence(&self, mime: &str) -> Option<u8> {
+        let (ty, subtype) = mime.split_once('/')?;
+        let (range_ty, range_subtype) = self.mime.split_once('/')?;
+
+        match (range_ty, range_subtype) {
+            ("*", "*") => Some(0),
+            (t, "*") if t.eq_ignore_ascii_case(ty) => Some(1),
+            (t, s) if t.eq_ignore_ascii_case(ty) && s.eq_ignore_ascii_case(subtype) => Some(2),
+            _ => None,
+        }
+    }
+}
+
+impl Accept {
+    /// Returns quality of the media type given by the most specific matching range,
+    /// 0 when no range matches.
+    pub fn quality(&self, mime: &str) -> f32 {
+        self.0
+            .iter()
+            .filter_map(|range| range.precedence(mime).map(|p| (p, range.quality)))
+            .max_by_key(|(precedence, _)| *precedence)
+            .map_or(0.0, |(_, quality)| quality)
+    }
+
+    /// Returns available media type client prefers the most, earlier one on a tie.
+    /// `None` when client accepts none of them.
+    pub fn preferred<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&'a str, f32)> = None;
+        for mime in available {
+            let quality = self.quality(mime);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((mime, quality));
+            }
+        }
+        best.map(|(mime, _)| mime)
+    }
+}
+
+impl Header for Accept {
+    fn key() -> HeaderName {
+        hyper::header::ACCEPT
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let mut ranges = vec![];
+        for range in header_value.to_str()?.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let mime = params.next().unwrap_or_default().to_ascii_lowercase();
+            if mime.is_empty() {
+                continue;
+            }
+            if mime.split('/').count() != 2 {
+                bail!("invalid media range {}", mime);
+            }
+
+            let mut quality = 1.0;
+            for param in params {
+                if let Some(q) = param.strip_prefix("q=").or(param.strip_prefix("Q=")) {
+                    quality = q.parse()?;
+                    if !(0.0..=1.0).contains(&quality) {
+                        bail!("quality {} out of range", q);
+                    }
+                }
+            }
+            ranges.push(MediaRange { mime, quality });
+        }
+
+        ranges.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+        Ok(Self(ranges))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        let value = self
+            .0
+            .iter()
+            .map(|range| match range.quality {
+                q if q >= 1.0 => range.mime.clone(),
+                q => format!("{};q={}", range.mime, q),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(HeaderValue::from_str(&value)?)
+    }
+
+    fn try_from_header_map(map: &HeaderMap<HeaderValue>) -> Result<Self, Rejection> {
+        try_from_joined_values(map, ", ")
+    }
+}
+
+/// Cookies sent by client, in order they were sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookie(pub Vec<(String, String)>);
+
+impl Cookie {
+    /// Returns value of the first cookie with given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl Header for Cookie {
+    fn key() -> HeaderName {
+        COOKIE
+    }
+
+    /// Pairs without `=` are skipped, quotes around values are removed.
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let cookies = header_value
+            .to_str()?
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                (name.trim().to_string(), value.to_string())
+            })
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        Ok(Self(cookies))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        let value = self
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Ok(HeaderValue::from_str(&value)?)
+    }
+
+    fn try_from_header_map(map: &HeaderMap<HeaderValue>) -> Result<Self, Rejection> {
+        try_from_joined_values(map, "; ")
+    }
+}
+
+/// Entity tags of representations client already has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfNoneMatch {
+    /// `*`, any representation.
+    Any,
+
+    /// Quoted tags, weak ones with `W/` prefix.
+    Tags(Vec<String>),
+}
+
+impl IfNoneMatch {
+    /// Compares tag with client's ones using weak comparison, i.e. ignoring `W/`.
+    /// Response should be 304 when it matches.
+    pub fn matches(&self, etag: &str) -> bool {
+        let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|tag| opaque(tag) == opaque(etag)),
+        }
+    }
+}
+
+impl Header for IfNoneMatch {
+    fn key() -> HeaderName {
+        IF_NONE_MATCH
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let value = header_value.to_str()?.trim();
+        if value == "*" {
+            return Ok(Self::Any);
+        }
+
+        let tags = value
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| {
+                let opaque = tag.strip_prefix("W/").unwrap_or(tag);
+                match opaque.len() >= 2 && opaque.starts_with('"') && opaque.ends_with('"') {
+                    true => Ok(tag.to_string()),
+                    false => Err(anyhow!("entity tag {} is not quoted", tag)),
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::Tags(tags))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        match self {
+            Self::Any => Ok(HeaderValue::from_static("*")),
+            Self::Tags(tags) => Ok(HeaderValue::from_str(&tags.join(", "))?),
+        }
+    }
+
+    fn try_from_header_map(map: &HeaderMap<HeaderValue>) -> Result<Self, Rejection> {
+        try_from_joined_values(map, ", ")
+    }
+}
+
+/// Date of representation client already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IfModifiedSince(pub SystemTime);
+
+impl IfModifiedSince {
+    /// Returns true when resource was modified after the date, with precision
+    /// of HTTP dates, i.e. seconds. Response should be 304 otherwise.
+    pub fn is_modified(&self, last_modified: SystemTime) -> bool {
+        let seconds = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        };
+        seconds(last_modified) > seconds(self.0)
+    }
+}
+
+impl Header for IfModifiedSince {
+    fn key() -> HeaderName {
+        IF_MODIFIED_SINCE
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        Ok(Self(httpdate::parse_http_date(header_value.to_str()?)?))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        Ok(HeaderValue::from_str(&httpdate::fmt_http_date(self.0))?)
+    }
+}
+
+/// Byte ranges of representation client asks for, only `bytes` unit is supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range(pub Vec<ByteRange>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive.
+    FromTo(u64, u64),
+
+    /// `first-`, till the end.
+    From(u64),
+
+    /// `-length`, last bytes.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Returns inclusive bounds of the range in representation of given length,
+    /// `None` when range is not satisfiable.
+    pub fn bounds(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            Self::FromTo(first, last) if first < length => Some((first, last.min(length - 1))),
+            Self::From(first) if first < length => Some((first, length - 1)),
+            Self::Last(count) if count > 0 && length > 0 => {
+                Some((length.saturating_sub(count), length - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for ByteRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FromTo(first, last) => write!(f, "{}-{}", first, last),
+            Self::From(first) => write!(f, "{}-", first),
+            Self::Last(count) => write!(f, "-{}", count),
+        }
+    }
+}
+
+impl Header for Range {
+    fn key() -> HeaderName {
+        RANGE
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let value = header_value.to_str()?;
+        let ranges = value
+            .trim()
+            .strip_prefix("bytes=")
+            .ok_or_else(|| anyhow!("unsupported range unit"))?;
+
+        let ranges = ranges
+            .split(',')
+            .map(str::trim)
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let range = match range.split_once('-') {
+                    Some(("", count)) => ByteRange::Last(count.parse()?),
+                    Some((first, "")) => ByteRange::From(first.parse()?),
+                    Some((first, last)) => {
+                        let (first, last) = (first.parse()?, last.parse()?);
+                        if first > last {
+                            bail!("invalid range {}", range);
+                        }
+                        ByteRange::FromTo(first, last)
+                    }
+                    None => bail!("invalid range {}", range),
+                };
+                Ok(range)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if ranges.is_empty() {
+            bail!("no ranges");
+        }
+        Ok(Self(ranges))
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        let ranges = self
+            .0
+            .iter()
+            .map(ByteRange::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(HeaderValue::from_str(&format!("bytes={}", ranges))?)
+    }
+}
+
+/// Origin of cross-origin request. Opaque origin `null` is treated as invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub scheme: String,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+impl Header for Origin {
+    fn key() -> HeaderName {
+        ORIGIN
+    }
+
+    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
+        let value = header_value.to_str()?.trim();
+        let (scheme, authority) = value
+            .split_once("://")
+            .ok_or_else(|| anyhow!("expected scheme://host[:port], got {}", value))?;
+        if scheme.is_empty() || authority.is_empty() || authority.contains('/') {
+            bail!("expected scheme://host[:port], got {}", value);
+        }
+
+        // IPv6 hosts are in brackets, e.g. `[::1]:8080`.
+        let port_separator = match authority.rfind(']') {
+            Some(end) => authority[end..].find(':').map(|i| end + i),
+            None => authority.rfind(':'),
+        };
+        let (host, port) = match port_separator {
+            Some(i) => (&authority[..i], Some(authority[i + 1..].parse()?)),
+            None => (authority, None),
+        };
+
+        Ok(Self {
+            scheme: scheme.to_ascii_lowercase(),
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+
+    fn to_header_value(&self) -> anyhow::Result<HeaderValue> {
+        Ok(HeaderValue::from_str(&self.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn parse<H: Header>(value: &'static str) -> anyhow::Result<H> {
+        H::try_from_header_value(&HeaderValue::from_static(value))
+    }
+
+    fn roundtrip<H: Header>(value: &'static str) -> String {
+        let header: H = parse(value).unwrap();
+        header
+            .to_header_value()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_authorization() {
+        assert_eq!(
+            parse::<Authorization>("Bearer abc.def").unwrap(),
+            Authorization::Bearer("abc.def".into())
+        );
+        assert_eq!(
+            parse::<Authorization>("basic am9objpzM2M6cmV0").unwrap(),
+            Authorization::Basic {
+                username: "john".into(),
+                password: "s3c:ret".into()
+            }
+        );
+        assert!(parse::<Authorization>("Basic !!!").is_err());
+        assert!(parse::<Authorization>("Digest abc").is_err());
+        assert_eq!(
+            roundtrip::<Authorization>("basic am9objpzM2M6cmV0"),
+            "Basic am9objpzM2M6cmV0"
+        );
+    }
+
+    #[test]
+    fn test_accept() {
+        let accept: Accept = parse("text/*;q=0.5, application/json, */*;q=0.1").unwrap();
+        assert_eq!(accept.0[0].mime, "application/json");
+        assert_eq!(accept.quality("text/html"), 0.5);
+        assert_eq!(accept.quality("image/png"), 0.1);
+        assert_eq!(
+            accept.preferred(&["text/html", "application/json"]),
+            Some("application/json")
+        );
+
+        let accept: Accept = parse("text/html;level=1;q=0").unwrap();
+        assert_eq!(accept.preferred(&["text/html"]), None);
+        assert!(parse::<Accept>("text/html;q=2").is_err());
+        assert!(parse::<Accept>("html").is_err());
+        assert_eq!(
+            roundtrip::<Accept>("text/*;q=0.5, application/json"),
+            "application/json, text/*;q=0.5"
+        );
+    }
+
+    #[test]
+    fn test_cookie_and_if_none_match() {
+        let cookie: Cookie = parse("session=abc; theme=\"dark\"; invalid").unwrap();
+        assert_eq!(cookie.get("session"), Some("abc"));
+        assert_eq!(cookie.get("theme"), Some("dark"));
+        assert_eq!(cookie.iter().count(), 2);
+
+        let mut map = HeaderMap::new();
+        map.append(COOKIE, HeaderValue::from_static("a=1"));
+        map.append(COOKIE, HeaderValue::from_static("b=2"));
+        let cookie = Cookie::try_from_header_map(&map).unwrap();
+        assert_eq!((cookie.get("a"), cookie.get("b")), (Some("1"), Some("2")));
+
+        let tags: IfNoneMatch = parse("\"v1\", W/\"v2\"").unwrap();
+        assert!(tags.matches("\"v2\""));
+        assert!(!tags.matches("\"v3\""));
+        assert!(parse::<IfNoneMatch>("*").unwrap().matches("\"v3\""));
+        assert!(parse::<IfNoneMatch>("v1").is_err());
+    }
+
+    #[test]
+    fn test_dates_ranges_and_origin() {
+        let since: IfModifiedSince = parse("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert!(!since.is_modified(since.0 + Duration::from_millis(500)));
+        assert!(since.is_modified(since.0 + Duration::from_secs(1)));
+        assert_eq!(
+            roundtrip::<IfModifiedSince>("Sun, 06 Nov 1994 08:49:37 GMT"),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+
+        let range: Range = parse("bytes=0-99, 200-, -50").unwrap();
+        assert_eq!(
+            range.0,
+            vec![
+                ByteRange::FromTo(0, 99),
+                ByteRange::From(200),
+                ByteRange::Last(50)
+            ]
+        );
+        assert_eq!(range.0[0].bounds(50), Some((0, 49)));
+        assert_eq!(range.0[1].bounds(100), None);
+        assert_eq!(range.0[2].bounds(30), Some((0, 29)));
+        assert!(parse::<Range>("bytes=10-5").is_err());
+        assert!(parse::<Range>("items=0-5").is_err());
+
+        let origin: Origin = parse("https://Example.com:8443").unwrap();
+        assert_eq!(
+            (origin.scheme.as_str(), origin.host.as_str(), origin.port),
+            ("https", "example.com", Some(8443))
+        );
+        assert_eq!(parse::<Origin>("http://[::1]").unwrap().host, "[::1]");
+        assert!(parse::<Origin>("null").is_err());
+        assert!(parse::<Origin>("https://example.com/path").is_err());
+
+        assert_eq!(parse::<ContentLength>("42").unwrap(), ContentLength(42));
+    }
+}
@@ -1,6 +1,19 @@
+pub mod body;
+pub mod cookies;
+pub mod error;
 pub mod handler;
+pub mod headers;
 pub mod middleware;
+pub mod multipart;
+pub mod negotiate;
+mod parser;
+mod path;
+mod pool;
 pub mod request;
 pub mod response;
 pub mod route;
 pub mod server;
+pub mod session;
+pub mod shutdown;
+pub mod tls;
+pub mod tree;
@@ -1,9 +1,13 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 use hyper::{Body, Request};
 use log::debug;
 
-use crate::response::Response;
+use crate::{
+    error::internal_error,
+    handler::{block_on, BoxFuture},
+    response::Response,
+};
 
 /// Splitting MiddlewareClone into its own trait allows us to provide a blanket
 /// implementation for all compatible types, without having to implement the
@@ -44,6 +48,146 @@ pub trait Middleware<B = Body>: MiddlewareClone + Send + Sync {
     }
 }
 
+/// Middleware that wraps the rest of request handling. It decides if and
+/// how many times `next` is run, so it can answer on its own (e.g. with 401),
+/// replace the response or retry.
+///
+/// Every `Middleware` is also an `AroundMiddleware`, its `on_request` runs
+/// before `next` and `on_response` after it. Closures can be used through
+/// `middleware::from_fn`.
+pub trait AroundMiddleware: Send + Sync {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> Response;
+
+    /// Runs middleware without blocking the thread, used when `is_blocking`
+    /// is false. By default `call` is run before the future is returned.
+    fn call_async<'a>(&'a self, req: Request<Body>, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(std::future::ready(self.call(req, next)))
+    }
+
+    /// Tells if `call` can block the thread. Requests that go only through
+    /// non-blocking middlewares to `async fn` handler are run by async server
+    /// on the runtime, middlewares have to await `Next::run_async` then.
+    fn is_blocking(&self) -> bool {
+        true
+    }
+}
+
+/// Route's handler called at the end of middleware chain.
+pub type Endpoint<'a> = dyn Fn(Request<Body>) -> BoxFuture<'a, Response> + Sync + 'a;
+
+/// Rest of the middleware chain, ending with route's handler.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn AroundMiddleware>],
+    endpoint: &'a Endpoint<'a>,
+}
+
+impl<'a> Next<'a> {
+    /// Creates chain that runs `middlewares` in order and `endpoint` after them.
+    pub fn new(middlewares: &'a [Arc<dyn AroundMiddleware>], endpoint: &'a Endpoint<'a>) -> Self {
+        Self {
+            middlewares,
+            endpoint,
+        }
+    }
+
+    /// Passes request to the next middleware or to the handler.
+    pub fn run(self, req: Request<Body>) -> Response {
+        match self.middlewares.split_first() {
+            Some((m, rest)) => m.call(req, Next::new(rest, self.endpoint)),
+            None => block_on((self.endpoint)(req)),
+        }
+    }
+
+    /// Asynchronous version of `run`.
+    pub fn run_async(self, req: Request<Body>) -> BoxFuture<'a, Response> {
+        match self.middlewares.split_first() {
+            Some((m, rest)) => m.call_async(req, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(req),
+        }
+    }
+}
+
+/// Adapts hooks of `Middleware`. Failing hook answers request with 500 status code.
+/// Hooks should not block, so these middlewares don't keep `async fn` handlers
+/// off the runtime.
+impl<T> AroundMiddleware for T
+where
+    T: Middleware,
+{
+    fn call(&self, mut req: Request<Body>, next: Next<'_>) -> Response {
+        if let Err(err) = self.on_request(&mut req) {
+            return internal_error(err);
+        }
+
+        let mut response = next.run(req);
+        if let Err(err) = self.on_response(&mut response) {
+            return internal_error(err);
+        }
+        response
+    }
+
+    fn call_async<'a>(&'a self, mut req: Request<Body>, next: Next<'a>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            if let Err(err) = self.on_request(&mut req) {
+                return internal_error(err);
+            }
+
+            let mut response = next.run_async(req).await;
+            if let Err(err) = self.on_response(&mut response) {
+                return internal_error(err);
+            }
+            response
+        })
+    }
+
+    fn is_blocking(&self) -> bool {
+        false
+    }
+}
+
+/// Creates middleware from a closure.
+///
+/// ```
+/// use core::middleware::{from_fn, Next};
+/// use core::route::Router;
+/// use hyper::{Body, Request, StatusCode};
+///
+/// fn handler() {}
+///
+/// let auth = from_fn(|req: Request<Body>, next: Next| {
+///     if req.headers().contains_key("authorization") {
+///         return next.run(req);
+///     }
+///
+///     hyper::Response::builder()
+///         .status(StatusCode::UNAUTHORIZED)
+///         .body(Body::empty())
+///         .unwrap()
+/// });
+///
+/// let app = Router::default().middleware(auth).get("/", handler);
+/// ```
+pub fn from_fn<F>(f: F) -> FromFn<F>
+where
+    F: Fn(Request<Body>, Next<'_>) -> Response + Send + Sync,
+{
+    FromFn(f)
+}
+
+/// Middleware created with `middleware::from_fn`.
+#[derive(Clone, Copy)]
+pub struct FromFn<F>(F);
+
+impl<F> AroundMiddleware for FromFn<F>
+where
+    F: Fn(Request<Body>, Next<'_>) -> Response + Send + Sync,
+{
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> Response {
+        (self.0)(req, next)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct LogMiddleware {}
 
@@ -0,0 +1,193 @@
+use crate::{
+    body::BodyStream,
+    request::{check_content_type, FromRequest, FromRequestAsync, Rejection},
+};
+use hyper::{body::Bytes, header::CONTENT_TYPE, Body, HeaderMap, Request};
+use std::{collections::HashMap, future::Future};
+
+/// Default maximum size of field's data, see `Multipart::field_limit`.
+pub const DEFAULT_FIELD_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Body of `multipart/form-data` request, read field by field as it arrives.
+/// Data of each field is limited, field that exceeds its limit rejects
+/// the request with 413. Whole body is limited by `body::BodyLimit`.
+///
+/// Requests with other `Content-Type` are rejected with 415,
+/// `multipart/form-data` without boundary with 400.
+///
+/// ```rust
+/// use core::multipart::Multipart;
+/// use core::request::Rejection;
+///
+/// async fn upload(multipart: Multipart) -> Result<String, Rejection> {
+///     let mut multipart = multipart
+///         .field_limit(64 * 1024)
+///         .limit_for("avatar", 10 * 1024 * 1024);
+///
+///     let mut uploaded = vec![];
+///     while let Some(mut field) = multipart.next_field().await? {
+///         let mut size = 0;
+///         while let Some(chunk) = field.chunk().await? {
+///             size += chunk.len();
+///         }
+///         uploaded.push(format!("{}: {}", field.name().unwrap_or_default(), size));
+///     }
+///     Ok(uploaded.join(", "))
+/// }
+/// ```
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+
+    /// Limit of fields without their own one.
+    field_limit: usize,
+
+    /// Limits of fields by their names.
+    limits: HashMap<String, usize>,
+}
+
+impl Multipart {
+    fn from_request(req: Request<Body>) -> Result<Self, Rejection> {
+        check_content_type(req.headers(), "multipart/form-data", |mime| {
+            mime == "multipart/form-data"
+        })?;
+
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| {
+                Rejection::unsupported_media_type("expected multipart/form-data body")
+            })?;
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|e| Rejection::bad_request(format!("invalid multipart body: {}", e)))?;
+
+        let body = BodyStream::new(req)?;
+
+        Ok(Self {
+            inner: multer::Multipart::new(body, boundary),
+            field_limit: DEFAULT_FIELD_LIMIT,
+            limits: HashMap::new(),
+        })
+    }
+
+    /// Sets maximum size of data of every field, `DEFAULT_FIELD_LIMIT` by default.
+    pub fn field_limit(mut self, limit: usize) -> Self {
+        self.field_limit = limit;
+        self
+    }
+
+    /// Sets maximum size of data of the field with given name.
+    pub fn limit_for(mut self, name: impl Into<String>, limit: usize) -> Self {
+        self.limits.insert(name.into(), limit);
+        self
+    }
+
+    /// Returns next field, `None` when there are no more fields.
+    /// Previous field has to be dropped before.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, Rejection> {
+        let field = match self.inner.next_field().await.map_err(multipart_rejection)? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+
+        let limit = field
+            .name()
+            .and_then(|name| self.limits.get(name))
+            .copied()
+            .unwrap_or(self.field_limit);
+
+        Ok(Some(Field {
+            inner: field,
+            limit,
+            read: 0,
+        }))
+    }
+}
+
+impl<S> FromRequest<Body, S> for Multipart {
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        Multipart::from_request(req)
+    }
+}
+
+impl<S> FromRequestAsync<Body, S> for Multipart {
+    fn from_request_async(
+        req: Request<Body>,
+        _state: &S,
+    ) -> impl Future<Output = Result<Self, Rejection>> + Send {
+        std::future::ready(Multipart::from_request(req))
+    }
+}
+
+/// Single field of multipart body, e.g. uploaded file.
+pub struct Field {
+    inner: multer::Field<'static>,
+    limit: usize,
+
+    /// Number of bytes of data already read.
+    read: usize,
+}
+
+impl Field {
+    /// Returns name of the field from its `Content-Disposition`.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Returns name of uploaded file from field's `Content-Disposition`.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// Returns field's `Content-Type`.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(|mime| mime.as_ref())
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// Returns next chunk of field's data, `None` when whole data was read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Rejection> {
+        let chunk = self.inner.chunk().await.map_err(multipart_rejection)?;
+
+        if let Some(chunk) = &chunk {
+            self.read += chunk.len();
+            if self.read > self.limit {
+                return Err(Rejection::payload_too_large(format!(
+                    "field {} is larger than {} bytes",
+                    self.name().unwrap_or_default(),
+                    self.limit
+                )));
+            }
+        }
+        Ok(chunk)
+    }
+
+    /// Reads whole field's data.
+    pub async fn bytes(mut self) -> Result<Bytes, Rejection> {
+        let mut data = vec![];
+        while let Some(chunk) = self.chunk().await? {
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.into())
+    }
+
+    /// Reads whole field's data as UTF-8 text.
+    pub async fn text(self) -> Result<String, Rejection> {
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| Rejection::bad_request(format!("field is not valid UTF-8: {}", e)))
+    }
+}
+
+/// Rejections of the underlying `BodyStream`, e.g. 413, are passed through.
+fn multipart_rejection(err: multer::Error) -> Rejection {
+    if let multer::Error::StreamReadFailed(err) = &err {
+        if let Some(rejection) = err.downcast_ref::<Rejection>() {
+            return rejection.clone();
+        }
+    }
+    Rejection::bad_request(format!("invalid multipart body: {}", err))
+}
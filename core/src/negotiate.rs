@@ -0,0 +1,133 @@
+use crate::{
+    headers::{Accept, Header},
+    request::{FromRequestParts, Rejection},
+    response::{Responder, Response},
+};
+use hyper::{
+    header::{CONTENT_TYPE, VARY},
+    http::request::Parts,
+    Body, StatusCode,
+};
+use serde::Serialize;
+
+/// Formats `Negotiate` responds with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `application/json`.
+    Json,
+
+    /// `application/x-www-form-urlencoded`, for flat structures only.
+    Form,
+
+    /// `application/x-bincode`.
+    Bincode,
+}
+
+impl Format {
+    /// All formats, the first one is used when client accepts any of them.
+    const ALL: [Format; 3] = [Format::Json, Format::Form, Format::Bincode];
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Form => "application/x-www-form-urlencoded",
+            Self::Bincode => "application/x-bincode",
+        }
+    }
+
+    /// Picks format client prefers, JSON when it didn't send `Accept` header.
+    /// `None` when client accepts none of the formats.
+    pub fn from_accept(accept: Option<&Accept>) -> Option<Self> {
+        let accept = match accept {
+            Some(accept) => accept,
+            None => return Some(Self::Json),
+        };
+
+        let mimes = Self::ALL.map(Self::mime);
+        let preferred = accept.preferred(&mimes)?;
+        Self::ALL
+            .into_iter()
+            .find(|format| format.mime() == preferred)
+    }
+
+    fn serialize<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec(value)?,
+            Self::Form => serde_urlencoded::to_string(value)?.into_bytes(),
+            Self::Bincode => bincode::serialize(value)?,
+        })
+    }
+}
+
+/// Responds with value serialized to format picked from request's `Accept` header.
+/// As an extractor it picks the format, request that accepts none of them is
+/// rejected with 406. Value is passed to `Negotiate::respond` then.
+///
+/// ```rust
+/// use core::negotiate::Negotiate;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// fn user(negotiate: Negotiate) -> Negotiate<User> {
+///     negotiate.respond(User {
+///         name: "john".into(),
+///     })
+/// }
+/// ```
+pub struct Negotiate<T = ()> {
+    format: Format,
+    value: T,
+}
+
+impl Negotiate {
+    /// Returns responder that sends the value in picked format.
+    pub fn respond<T>(self, value: T) -> Negotiate<T> {
+        Negotiate::new(self.format, value)
+    }
+}
+
+impl<T> Negotiate<T> {
+    pub fn new(format: Format, value: T) -> Self {
+        Self { format, value }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+}
+
+/// Invalid `Accept` header is treated as missing.
+impl<S> FromRequestParts<S> for Negotiate {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        let accept = Accept::try_from_header_map(&parts.headers).ok();
+
+        match Format::from_accept(accept.as_ref()) {
+            Some(format) => Ok(Negotiate::new(format, ())),
+            None => Err(Rejection::new(
+                StatusCode::NOT_ACCEPTABLE,
+                format!(
+                    "expected one of {} to be accepted",
+                    Format::ALL.map(Format::mime).join(", ")
+                ),
+            )),
+        }
+    }
+}
+
+/// Failed serialization is an unhandled error.
+impl<T> Responder for Negotiate<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> anyhow::Result<Response> {
+        let body = self.format.serialize(&self.value)?;
+        Ok(hyper::Response::builder()
+            .header(CONTENT_TYPE, self.format.mime())
+            .header(VARY, "accept")
+            .body(Body::from(body))?)
+    }
+}
@@ -0,0 +1,576 @@
+use bytes::{Buf, Bytes, BytesMut};
+use hyper::{
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING},
+    http::request::Parts,
+    Method, Request, StatusCode, Uri, Version,
+};
+use std::fmt::Display;
+
+/// Limits enforced while parsing incoming requests.
+#[derive(Debug, Clone, Copy)]
+pub struct ParseLimits {
+    /// Maximum size of request line and all header fields together.
+    pub max_header_size: usize,
+
+    /// Maximum number of header fields.
+    pub max_headers: usize,
+
+    /// Maximum size of decoded request body, `None` leaves it to the routes.
+    pub max_body_size: Option<usize>,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_header_size: 8 * 1024,
+            max_headers: 64,
+            max_body_size: None,
+        }
+    }
+}
+
+/// Error returned when request can't be parsed. Carries status code that
+/// should be sent back to the client before closing the connection.
+#[derive(Debug)]
+pub struct ParseError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new<M: Display>(status: StatusCode, message: M) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn bad_request<M: Display>(message: M) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    /// Error for request that was not fully received before read timeout.
+    pub fn timeout() -> Self {
+        Self::new(
+            StatusCode::REQUEST_TIMEOUT,
+            "request was not received in time",
+        )
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Incremental HTTP/1.1 request parser. It does not do any IO by itself,
+/// bytes read from the connection are passed to `RequestParser::parse`
+/// which consumes request's head once it's complete. Body is not buffered,
+/// it's decoded piece by piece with returned `BodyDecoder`.
+///
+/// Head is parsed once the empty line that ends it arrived, search for that
+/// line resumes where the previous call stopped, so a head that arrives
+/// in many small pieces is not scanned from the beginning again.
+#[derive(Debug)]
+pub struct RequestParser {
+    limits: ParseLimits,
+
+    /// Number of buffered bytes already searched for the empty line that ends the head.
+    scanned: usize,
+}
+
+/// Decodes body of one request as its bytes arrive.
+#[derive(Debug)]
+pub struct BodyDecoder {
+    framing: Framing,
+
+    /// Size of body decoded so far.
+    decoded: usize,
+    max_body_size: Option<usize>,
+}
+
+#[derive(Debug)]
+enum Framing {
+    /// Body with `Content-Length`, holds number of bytes that are still missing.
+    Length(usize),
+
+    /// Body with `Transfer-Encoding: chunked`.
+    Chunked(Chunk),
+}
+
+#[derive(Debug)]
+enum Chunk {
+    /// Waiting for chunk size line.
+    Size,
+
+    /// Reading chunk data, holds number of bytes that are still missing.
+    Data(usize),
+
+    /// Waiting for CRLF that ends chunk data.
+    DataEnd,
+
+    /// Last chunk was read, skipping trailer fields until empty line.
+    Trailers,
+
+    /// Whole body was read.
+    End,
+}
+
+impl RequestParser {
+    pub fn new(limits: ParseLimits) -> Self {
+        Self { limits, scanned: 0 }
+    }
+
+    /// Indicates if parser is not in the middle of request's head.
+    pub fn is_idle(&self) -> bool {
+        self.scanned == 0
+    }
+
+    /// Consumes request's head from `buf` once it is complete and returns it
+    /// together with decoder of its body. `Ok(None)` means more bytes are needed.
+    pub fn parse(
+        &mut self,
+        buf: &mut BytesMut,
+    ) -> Result<Option<(Parts, BodyDecoder)>, ParseError> {
+        // Empty lines before request line are ignored.
+        if self.scanned == 0 {
+            let blank = buf
+                .iter()
+                .take_while(|b| matches!(b, b'\r' | b'\n'))
+                .count();
+            buf.advance(blank);
+        }
+
+        // httparse always starts from the beginning, so it's run only once the whole
+        // head is buffered. Line ending may be split between reads, hence the overlap.
+        if find_head_end(buf, self.scanned.saturating_sub(2)).is_none() {
+            self.scanned = buf.len();
+            if buf.len() > self.limits.max_header_size {
+                return Err(ParseError::new(
+                    StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                    "request header is too large",
+                ));
+            }
+            return Ok(None);
+        }
+        self.scanned = 0;
+
+        let mut headers = vec![httparse::EMPTY_HEADER; self.limits.max_headers];
+        let mut req = httparse::Request::new(&mut headers);
+
+        let head_len = match req.parse(buf) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => {
+                return Err(ParseError::bad_request("incomplete request head"))
+            }
+            Err(httparse::Error::TooManyHeaders) => {
+                return Err(ParseError::new(
+                    StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                    "too many header fields",
+                ))
+            }
+            Err(e) => return Err(ParseError::bad_request(e)),
+        };
+
+        if head_len > self.limits.max_header_size {
+            return Err(ParseError::new(
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                "request header is too large",
+            ));
+        }
+
+        let (mut parts, _) = Request::new(()).into_parts();
+        parts.method = Method::from_bytes(req.method.unwrap_or_default().as_bytes())
+            .map_err(ParseError::bad_request)?;
+        parts.uri = req
+            .path
+            .unwrap_or_default()
+            .parse::<Uri>()
+            .map_err(ParseError::bad_request)?;
+        parts.version = match req.version {
+            Some(0) => Version::HTTP_10,
+            _ => Version::HTTP_11,
+        };
+        for header in req.headers.iter() {
+            parts.headers.append(
+                HeaderName::from_bytes(header.name.as_bytes()).map_err(ParseError::bad_request)?,
+                HeaderValue::from_bytes(header.value).map_err(ParseError::bad_request)?,
+            );
+        }
+        buf.advance(head_len);
+
+        let framing = self.framing(&parts)?;
+
+        // Transfer-Encoding has a priority over Content-Length, the latter must
+        // not be passed further as it does not describe the decoded body.
+        if let Framing::Chunked(_) = framing {
+            parts.headers.remove(CONTENT_LENGTH);
+        }
+
+        let decoder = BodyDecoder {
+            framing,
+            decoded: 0,
+            max_body_size: self.limits.max_body_size,
+        };
+        Ok(Some((parts, decoder)))
+    }
+
+    /// Decides how request's body is delimited.
+    fn framing(&self, parts: &Parts) -> Result<Framing, ParseError> {
+        let transfer_encoding = parts.headers.get_all(TRANSFER_ENCODING);
+        if transfer_encoding.iter().next().is_some() {
+            let last_coding = transfer_encoding
+                .iter()
+                .flat_map(|v| v.to_str().unwrap_or_default().split(','))
+                .map(|c| c.trim().to_ascii_lowercase())
+                .next_back();
+
+            // Request body length can't be determined if chunked is not the final encoding.
+            return match last_coding.as_deref() {
+                Some("chunked") => Ok(Framing::Chunked(Chunk::Size)),
+                _ => Err(ParseError::bad_request("unsupported transfer encoding")),
+            };
+        }
+
+        let mut content_length = None;
+        for value in parts.headers.get_all(CONTENT_LENGTH) {
+            for v in value.to_str().unwrap_or_default().split(',') {
+                let length = v
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| ParseError::bad_request("invalid content-length"))?;
+
+                match content_length {
+                    Some(l) if l != length => {
+                        return Err(ParseError::bad_request("conflicting content-length"))
+                    }
+                    _ => content_length = Some(length),
+                }
+            }
+        }
+
+        let content_length = content_length.unwrap_or_default();
+        if self
+            .limits
+            .max_body_size
+            .is_some_and(|max| content_length > max)
+        {
+            return Err(body_too_large());
+        }
+
+        Ok(Framing::Length(content_length))
+    }
+}
+
+impl BodyDecoder {
+    /// Indicates if whole body was decoded.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.framing,
+            Framing::Length(0) | Framing::Chunked(Chunk::End)
+        )
+    }
+
+    /// Returns whole body if it's already buffered, as it's the case of most
+    /// small requests. Only bodies with `Content-Length` are taken this way.
+    pub fn take_buffered(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        match &mut self.framing {
+            Framing::Length(remaining) if *remaining <= buf.len() => {
+                let body = buf.split_to(*remaining).freeze();
+                self.decoded += body.len();
+                *remaining = 0;
+                Some(body)
+            }
+            _ => None,
+        }
+    }
+
+    /// Takes next piece of the body from `buf`. `Ok(None)` means that more bytes
+    /// are needed, or that body is finished, see `BodyDecoder::is_finished`.
+    /// Bytes that belong to the next (pipelined) request are left in the buffer.
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, ParseError> {
+        let chunk = match &mut self.framing {
+            Framing::Length(remaining) => {
+                let n = (*remaining).min(buf.len());
+                *remaining -= n;
+                buf.split_to(n).freeze()
+            }
+            Framing::Chunked(chunk) => {
+                match decode_chunked(chunk, buf, self.decoded, self.max_body_size)? {
+                    Some(data) => data,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        self.decoded += chunk.len();
+        Ok(Some(chunk))
+    }
+}
+
+fn body_too_large() -> ParseError {
+    ParseError::new(StatusCode::PAYLOAD_TOO_LARGE, "request body is too large")
+}
+
+/// Returns position right after the empty line that ends request's head,
+/// searching from `from`. Both CRLF and bare LF line endings are accepted.
+fn find_head_end(buf: &[u8], from: usize) -> Option<usize> {
+    (from..buf.len()).find_map(|i| match &buf[i..] {
+        [b'\n', b'\n', ..] => Some(i + 2),
+        [b'\n', b'\r', b'\n', ..] => Some(i + 3),
+        _ => None,
+    })
+}
+
+/// Decodes chunked body until next piece of data, `decoded` bytes of it were
+/// already read. Returns `None` when more bytes are needed or body has ended.
+fn decode_chunked(
+    chunk: &mut Chunk,
+    buf: &mut BytesMut,
+    decoded: usize,
+    max_body_size: Option<usize>,
+) -> Result<Option<Bytes>, ParseError> {
+    loop {
+        match chunk {
+            Chunk::Size => {
+                let line = match take_line(buf)? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                };
+                // Chunk extensions are allowed after ';', we just ignore them.
+                let size = line.split(';').next().unwrap_or_default().trim();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| ParseError::bad_request("invalid chunk size"))?;
+
+                if max_body_size.is_some_and(|max| decoded.saturating_add(size) > max) {
+                    return Err(body_too_large());
+                }
+
+                *chunk = match size {
+                    0 => Chunk::Trailers,
+                    size => Chunk::Data(size),
+                };
+            }
+            Chunk::Data(remaining) => {
+                let n = (*remaining).min(buf.len());
+                if n == 0 {
+                    return Ok(None);
+                }
+                *remaining -= n;
+                if *remaining == 0 {
+                    *chunk = Chunk::DataEnd;
+                }
+                return Ok(Some(buf.split_to(n).freeze()));
+            }
+            Chunk::DataEnd => {
+                if buf.len() < 2 {
+                    return Ok(None);
+                }
+                if &buf[..2] != b"\r\n" {
+                    return Err(ParseError::bad_request("chunk data not terminated"));
+                }
+                buf.advance(2);
+                *chunk = Chunk::Size;
+            }
+            Chunk::Trailers => match take_line(buf)? {
+                Some(line) if line.is_empty() => *chunk = Chunk::End,
+                Some(_) => {}
+                None => return Ok(None),
+            },
+            Chunk::End => return Ok(None),
+        }
+    }
+}
+
+/// Longest line accepted in chunked encoding framing (chunk size or trailer field).
+const MAX_LINE_SIZE: usize = 4096;
+
+/// Takes single CRLF terminated line from the buffer, returns it without CRLF.
+fn take_line(buf: &mut BytesMut) -> Result<Option<String>, ParseError> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if buf.len() > MAX_LINE_SIZE => {
+            return Err(ParseError::bad_request("chunk line is too long"))
+        }
+        None => return Ok(None),
+    };
+
+    let line = String::from_utf8(buf[..end].to_vec())
+        .map_err(|_| ParseError::bad_request("invalid chunk line"))?;
+    buf.advance(end + 2);
+
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BodyDecoder, ParseLimits, RequestParser};
+    use bytes::BytesMut;
+    use hyper::{http::request::Parts, StatusCode};
+
+    fn limits() -> ParseLimits {
+        ParseLimits {
+            max_header_size: 256,
+            max_headers: 4,
+            max_body_size: Some(16),
+        }
+    }
+
+    /// Feeds `raw` to the parser byte by byte, returns requests with their bodies.
+    fn parse_in_pieces(raw: &str) -> Vec<(Parts, Vec<u8>)> {
+        let mut parser = RequestParser::new(limits());
+        let mut buf = BytesMut::new();
+        let mut requests: Vec<(Parts, Vec<u8>)> = vec![];
+        let mut body: Option<BodyDecoder> = None;
+
+        for b in raw.bytes() {
+            buf.extend_from_slice(&[b]);
+            loop {
+                match &mut body {
+                    None => match parser.parse(&mut buf).expect("valid head") {
+                        Some((parts, decoder)) => {
+                            requests.push((parts, vec![]));
+                            body = Some(decoder);
+                        }
+                        None => break,
+                    },
+                    Some(decoder) => {
+                        while let Some(chunk) = decoder.decode(&mut buf).expect("valid body") {
+                            requests.last_mut().unwrap().1.extend_from_slice(&chunk);
+                        }
+                        if !decoder.is_finished() {
+                            break;
+                        }
+                        body = None;
+                    }
+                }
+            }
+        }
+
+        assert!(body.is_none() && parser.is_idle() && buf.is_empty());
+        requests
+    }
+
+    #[test]
+    fn test_parse_in_pieces() {
+        let requests = parse_in_pieces(
+            "POST /body HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET /next HTTP/1.1\r\n\r\n",
+        );
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].0.uri, "/body");
+        assert_eq!(requests[0].1, b"hello");
+        assert_eq!(requests[1].0.uri, "/next");
+        assert!(requests[1].1.is_empty());
+
+        let requests = parse_in_pieces(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: value\r\n\r\n",
+        );
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1, b"hello world");
+    }
+
+    #[test]
+    fn test_parse_blank_lines_and_bare_lf() {
+        let requests = parse_in_pieces("\r\nGET /lf HTTP/1.1\nHost: localhost\n\n");
+
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0.uri, "/lf");
+        assert_eq!(requests[0].0.headers["host"], "localhost");
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let mut parser = RequestParser::new(limits());
+        let mut buf = BytesMut::from(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n\
+             5\r\nhello\r\n6\r\n world\r\n0\r\n\r\nGET /next HTTP/1.1\r\n\r\n",
+        );
+
+        let (parts, mut decoder) = parser.parse(&mut buf).unwrap().expect("complete head");
+        assert!(!parts.headers.contains_key("content-length"));
+        assert_eq!(decoder.take_buffered(&mut buf), None);
+        assert_eq!(decoder.decode(&mut buf).unwrap().unwrap(), "hello");
+        assert_eq!(decoder.decode(&mut buf).unwrap().unwrap(), " world");
+        assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+        assert!(decoder.is_finished());
+        assert_eq!(&buf[..], b"GET /next HTTP/1.1\r\n\r\n");
+
+        let mut buf = BytesMut::from("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel");
+        let (_, mut decoder) = parser.parse(&mut buf).unwrap().expect("complete head");
+        assert_eq!(decoder.take_buffered(&mut buf), None);
+        buf.extend_from_slice(b"loGET");
+        assert_eq!(decoder.take_buffered(&mut buf).unwrap(), "hello");
+        assert!(decoder.is_finished());
+        assert_eq!(&buf[..], b"GET");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            (
+                "GET / HTTP/1.1\r\nBad Header\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n",
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n11\r\n",
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nab\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n",
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ),
+        ];
+
+        for (raw, status) in cases {
+            let mut parser = RequestParser::new(limits());
+            let mut buf = BytesMut::from(raw);
+            let err = match parser.parse(&mut buf) {
+                Ok(Some((_, mut decoder))) => loop {
+                    match decoder.decode(&mut buf) {
+                        Ok(Some(_)) => continue,
+                        decoded => break decoded.map(|_| ()),
+                    }
+                },
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            }
+            .expect_err("invalid request");
+            assert_eq!(err.status, status, "case: {}", raw);
+        }
+
+        let mut parser = RequestParser::new(limits());
+        let long_header = format!("GET / HTTP/1.1\r\nA: {}", "a".repeat(300));
+        let err = parser
+            .parse(&mut BytesMut::from(long_header.as_str()))
+            .expect_err("too long header");
+        assert_eq!(err.status, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+}
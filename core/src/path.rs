@@ -0,0 +1,381 @@
+use crate::request::Rejection;
+use percent_encoding::percent_decode_str;
+use serde::{
+    de::{self, value::BorrowedStrDeserializer, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use std::{fmt::Display, slice::Iter};
+
+/// Params of matched route kept in request's extensions: names with raw,
+/// still percent-encoded values, in order of appearance in the path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UrlParams(pub(crate) Vec<(String, String)>);
+
+impl UrlParams {
+    /// Percent-decodes values, fails when decoded value is not valid UTF-8.
+    pub(crate) fn decode(&self) -> Result<Vec<(String, String)>, Rejection> {
+        self.0
+            .iter()
+            .map(|(name, value)| {
+                let decoded = percent_decode_str(value).decode_utf8().map_err(|_| {
+                    Rejection::bad_request(format!("path param {} is not valid UTF-8", name))
+                })?;
+                Ok((name.clone(), decoded.into_owned()))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PathError(String);
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl de::Error for PathError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Deserializes decoded params. Structs and maps are filled by params' names,
+/// tuples and sequences by their order, other types need exactly one param.
+pub(crate) struct PathDeserializer<'de> {
+    params: &'de [(String, String)],
+}
+
+impl<'de> PathDeserializer<'de> {
+    pub(crate) fn new(params: &'de [(String, String)]) -> Self {
+        Self { params }
+    }
+
+    fn single(&self) -> Result<Value<'de>, PathError> {
+        match self.params {
+            [(name, value)] => Ok(Value { name, value }),
+            params => Err(PathError(format!(
+                "expected 1 path param, found {}",
+                params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! deserialize_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for PathDeserializer<'de> {
+    type Error = PathError;
+
+    deserialize_single!(
+        deserialize_any deserialize_bool deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_identifier
+    );
+
+    /// `None` when route's optional params are missing.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.params.is_empty() {
+            true => visitor.visit_none(),
+            false => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Params {
+            params: self.params.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if self.params.len() != len {
+            return Err(PathError(format!(
+                "expected {} path params, found {}",
+                len,
+                self.params.len()
+            )));
+        }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Params {
+            params: self.params.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+}
+
+/// Access to params as a sequence of values or as a map of names to values.
+struct Params<'de> {
+    params: Iter<'de, (String, String)>,
+
+    /// Param which name was returned as a map's key.
+    value: Option<&'de (String, String)>,
+}
+
+impl<'de> SeqAccess<'de> for Params<'de> {
+    type Error = PathError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.params.next() {
+            Some((name, value)) => seed.deserialize(Value { name, value }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.params.len())
+    }
+}
+
+impl<'de> MapAccess<'de> for Params<'de> {
+    type Error = PathError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.params.next() {
+            Some(param) => {
+                self.value = Some(param);
+                seed.deserialize(BorrowedStrDeserializer::new(&param.0))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (name, value) = self
+            .value
+            .take()
+            .ok_or_else(|| PathError("value requested before key".into()))?;
+        seed.deserialize(Value { name, value })
+    }
+}
+
+/// Single param's value, parsed into the type visitor asks for.
+struct Value<'de> {
+    name: &'de str,
+    value: &'de str,
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let parsed = self.value.parse::<$ty>().map_err(|_| {
+                    PathError(format!(
+                        "cannot parse path param {} with value {:?} as {}",
+                        self.name,
+                        self.value,
+                        stringify!($ty)
+                    ))
+                })?;
+                visitor.$visit(parsed)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value<'de> {
+    type Error = PathError;
+
+    deserialize_parsed!(
+        deserialize_bool => visit_bool(bool),
+        deserialize_char => visit_char(char),
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64),
+    );
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.value))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathDeserializer, UrlParams};
+    use serde::Deserialize;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        let raw = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        UrlParams(raw).decode().unwrap()
+    }
+
+    fn from_params<'de, T: Deserialize<'de>>(params: &'de [(String, String)]) -> Result<T, String> {
+        T::deserialize(PathDeserializer::new(params)).map_err(|e| e.to_string())
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        name: String,
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Admin,
+        Guest,
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let pairs = params(&[("id", "7"), ("name", "John%20Doe")]);
+        assert_eq!(
+            from_params::<(u32, String)>(&pairs),
+            Ok((7, "John Doe".into()))
+        );
+        assert_eq!(
+            from_params::<User>(&pairs),
+            Ok(User {
+                name: "John Doe".into(),
+                id: 7
+            })
+        );
+        assert_eq!(
+            from_params::<Vec<String>>(&pairs),
+            Ok(vec!["7".into(), "John Doe".into()])
+        );
+
+        let single = params(&[("kind", "guest")]);
+        assert_eq!(from_params::<Kind>(&single), Ok(Kind::Guest));
+        assert_eq!(from_params::<String>(&single), Ok("guest".into()));
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let pairs = params(&[("id", "abc"), ("name", "john")]);
+        assert_eq!(
+            from_params::<(u32, String)>(&pairs),
+            Err("cannot parse path param id with value \"abc\" as u32".into())
+        );
+        assert_eq!(
+            from_params::<u32>(&pairs),
+            Err("expected 1 path param, found 2".into())
+        );
+        assert_eq!(
+            from_params::<(String, String, String)>(&pairs),
+            Err("expected 3 path params, found 2".into())
+        );
+
+        let invalid = UrlParams(vec![("name".into(), "%FF".into())]);
+        assert!(invalid.decode().is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Fixed-size pool of worker threads fed through a bounded queue.
+///
+/// Every task sent to the pool is passed to the same handler function.
+/// When all workers are busy and the queue is full, task is given back
+/// to the caller so it can be rejected.
+pub struct WorkerPool<T> {
+    sender: Option<SyncSender<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T> WorkerPool<T>
+where
+    T: Send + 'static,
+{
+    /// Spawns `size` workers named `{name}-{index}`. At most `queue_depth` tasks
+    /// wait for a free worker, with depth 0 task is accepted only if some
+    /// worker is idle at the moment.
+    pub fn new<F>(size: usize, queue_depth: usize, name: &str, handler: F) -> std::io::Result<Self>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..size.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                let handler = handler.clone();
+                thread::Builder::new()
+                    .name(format!("{}-{}", name, i))
+                    .spawn(move || work(&receiver, handler.as_ref()))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            sender: Some(sender),
+            workers,
+        })
+    }
+
+    /// Passes task to the pool. Task is returned back if it can't be accepted.
+    pub fn try_dispatch(&self, task: T) -> Result<(), T> {
+        match &self.sender {
+            Some(sender) => sender.try_send(task).map_err(|e| match e {
+                TrySendError::Full(task) | TrySendError::Disconnected(task) => task,
+            }),
+            None => Err(task),
+        }
+    }
+
+    /// Closes the queue and waits until queued tasks are handled and all workers
+    /// finish, but no longer than `timeout`. Returns false when timeout passed,
+    /// workers that are still busy are detached then.
+    pub fn join_timeout(mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+
+        let deadline = Instant::now() + timeout;
+        while self.workers.iter().any(|w| !w.is_finished()) {
+            if Instant::now() >= deadline {
+                self.workers.clear();
+                return false;
+            }
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        true
+    }
+}
+
+/// How often `WorkerPool::join_timeout` checks if workers finished.
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Worker's loop, takes tasks from the queue until pool is dropped.
+fn work<T, F>(receiver: &Mutex<Receiver<T>>, handler: &F)
+where
+    F: Fn(T),
+{
+    loop {
+        // Lock is released right after receiving, before the task is handled.
+        let task = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+
+        match task {
+            Ok(task) => handler(task),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Dropping the pool closes the queue, waits until queued tasks
+/// are handled and all workers finish.
+impl<T> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkerPool;
+    use std::sync::{mpsc, Mutex};
+
+    #[test]
+    fn test_bounded_queue() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (release_rx, started_tx) = (Mutex::new(release_rx), Mutex::new(started_tx));
+
+        let pool = WorkerPool::new(1, 1, "test-worker", move |task: usize| {
+            started_tx.lock().unwrap().send(task).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+        })
+        .unwrap();
+
+        // First task occupies the only worker, second waits in the queue.
+        assert!(pool.try_dispatch(1).is_ok());
+        assert_eq!(started_rx.recv().unwrap(), 1);
+        assert!(pool.try_dispatch(2).is_ok());
+        assert_eq!(pool.try_dispatch(3), Err(3));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(started_rx.recv().unwrap(), 2);
+        release_tx.send(()).unwrap();
+        drop(pool);
+    }
+}
@@ -1,12 +1,18 @@
-use anyhow::{Context, Ok};
+use crate::{
+    body::read_body,
+    headers::Header,
+    path::{PathDeserializer, UrlParams},
+    response::{Responder, Response},
+};
 use hyper::{
-    body::Bytes,
-    header::{HeaderName, CONTENT_TYPE, HOST},
-    http::{request::Parts, HeaderValue},
-    Body, HeaderMap, Request,
+    body::Bytes, header::CONTENT_TYPE, http::request::Parts, Body, HeaderMap, Request, StatusCode,
 };
-use serde::de::DeserializeOwned;
-use std::{collections::HashMap, str::FromStr};
+use log::debug;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt::Display, future::Future, str::FromStr};
+
+pub use crate::headers::{ContentType, Host};
+pub use macros::FromRef;
 
 mod private {
     #[derive(Debug, Clone, Copy)]
@@ -16,19 +22,181 @@ mod private {
     pub enum ViaParts {}
 }
 
+/// Error returned by extractors that couldn't be created from request.
+/// Handler is not called then and rejection is sent back as a response
+/// with its status code and message as a body.
+///
+/// Handlers that want to deal with failures themselves can take
+/// `Option<T>` or `Result<T, Rejection>` instead of the extractor `T`.
+///
+/// ```rust
+/// use core::request::{Json, Rejection};
+///
+/// fn handler(body: Result<Json<i32>, Rejection>) -> String {
+///     match body {
+///         Ok(Json(value)) => value.to_string(),
+///         Err(rejection) => format!("invalid body: {}", rejection),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    status: StatusCode,
+    message: String,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Request is malformed, e.g. required header is missing. Status 400.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    /// Body has unexpected `Content-Type`. Status 415.
+    pub fn unsupported_media_type(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, message)
+    }
+
+    /// Body or one of its parts is larger than allowed. Status 413.
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::PAYLOAD_TOO_LARGE, message)
+    }
+
+    /// Body is well-formed, but its content is invalid. Status 422.
+    pub fn unprocessable_entity(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    /// Extractor could not be created because of server's fault,
+    /// e.g. it was used with a route that does not provide it. Status 500.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Errors of extractors built with `anyhow` are treated as client's fault.
+impl From<anyhow::Error> for Rejection {
+    fn from(err: anyhow::Error) -> Self {
+        Self::bad_request(err.to_string())
+    }
+}
+
+impl From<Rejection> for Response {
+    fn from(rejection: Rejection) -> Self {
+        debug!("request rejected: {}", rejection);
+
+        let mut response = Response::new(Body::from(rejection.message));
+        *response.status_mut() = rejection.status;
+        response
+    }
+}
+
+impl Responder for Rejection {
+    fn into_response(self) -> anyhow::Result<Response> {
+        Ok(self.into())
+    }
+}
+
 /// Allows various types to be created from Request.
 pub trait FromRequest<B, S, M = private::ViaRequest>: Sized {
-    fn from_request(req: Request<B>, state: &S) -> anyhow::Result<Self>;
+    fn from_request(req: Request<B>, state: &S) -> Result<Self, Rejection>;
+}
+
+/// Asynchronous counterpart of `FromRequest`, parameters of `async fn` handlers
+/// are created with it. Body is awaited instead of blocking the thread.
+pub trait FromRequestAsync<B, S, M = private::ViaRequest>: Sized {
+    fn from_request_async(
+        req: Request<B>,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Rejection>> + Send;
 }
 
 /// Implement FromRequest for every variant of Request<B>.
 impl<B, S> FromRequest<B, S> for Request<B> {
-    fn from_request(req: Request<B>, _state: &S) -> anyhow::Result<Self> {
+    fn from_request(req: Request<B>, _state: &S) -> Result<Self, Rejection> {
         Ok(req)
     }
 }
 
+impl<B, S> FromRequestAsync<B, S> for Request<B>
+where
+    B: Send,
+{
+    fn from_request_async(
+        req: Request<B>,
+        _state: &S,
+    ) -> impl Future<Output = Result<Self, Rejection>> + Send {
+        std::future::ready(Ok(req))
+    }
+}
+
+/// Failing extractor gives `None` instead of rejecting the request.
+impl<B, S, T> FromRequest<B, S> for Option<T>
+where
+    T: FromRequest<B, S>,
+{
+    fn from_request(req: Request<B>, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request(req, state).ok())
+    }
+}
+
+/// Rejection of failing extractor is passed to the handler.
+impl<B, S, T> FromRequest<B, S> for Result<T, Rejection>
+where
+    T: FromRequest<B, S>,
+{
+    fn from_request(req: Request<B>, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request(req, state))
+    }
+}
+
+impl<B, S, T> FromRequestAsync<B, S> for Option<T>
+where
+    B: Send,
+    S: Sync,
+    T: FromRequestAsync<B, S>,
+{
+    async fn from_request_async(req: Request<B>, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request_async(req, state).await.ok())
+    }
+}
+
+impl<B, S, T> FromRequestAsync<B, S> for Result<T, Rejection>
+where
+    B: Send,
+    S: Sync,
+    T: FromRequestAsync<B, S>,
+{
+    async fn from_request_async(req: Request<B>, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request_async(req, state).await)
+    }
+}
+
 /// Implement FromRequest for String for B in Body variant.
+/// Body has to be text, requests with `Content-Type` other than `text/*`
+/// are rejected with 415, bodies that are not valid UTF-8 with 400.
 ///
 /// This allows to create handler like that:
 ///
@@ -36,11 +204,62 @@ impl<B, S> FromRequest<B, S> for Request<B> {
 /// fn handler(s: String) {}
 /// ```
 impl<S> FromRequest<Body, S> for String {
-    fn from_request(req: Request<Body>, _state: &S) -> anyhow::Result<Self> {
-        let bytes: Bytes = futures_executor::block_on(hyper::body::to_bytes(req.into_body()))?;
-        let string = std::str::from_utf8(&bytes)?.to_owned();
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_text_content_type(req.headers())?;
+
+        let bytes = futures_executor::block_on(read_body(req))?;
+        string_from_bytes(&bytes)
+    }
+}
+
+impl<S> FromRequestAsync<Body, S> for String
+where
+    S: Sync,
+{
+    async fn from_request_async(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_text_content_type(req.headers())?;
+
+        let bytes = read_body(req).await?;
+        string_from_bytes(&bytes)
+    }
+}
+
+fn check_text_content_type(headers: &HeaderMap) -> Result<(), Rejection> {
+    check_content_type(headers, "text", |mime| mime.starts_with("text/"))
+}
+
+/// Raw body, accepted with any `Content-Type`. Bytes are not interpreted in any
+/// way, so there is no type they could mismatch, handler checks it if it cares.
+///
+/// ```rust
+/// use hyper::body::Bytes;
+///
+/// fn handler(body: Bytes) -> String {
+///     body.len().to_string()
+/// }
+/// ```
+impl<S> FromRequest<Body, S> for Bytes {
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        futures_executor::block_on(read_body(req))
+    }
+}
 
-        Ok(string)
+impl<S> FromRequestAsync<Body, S> for Bytes
+where
+    S: Sync,
+{
+    async fn from_request_async(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        read_body(req).await
+    }
+}
+
+fn string_from_bytes(bytes: &Bytes) -> Result<String, Rejection> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(s.to_owned()),
+        Err(e) => Err(Rejection::bad_request(format!(
+            "body is not valid UTF-8: {}",
+            e
+        ))),
     }
 }
 
@@ -48,94 +267,223 @@ impl<S> FromRequest<Body, S> for String {
 /// It implements FromRequest<Body> in order to allow user quick and easy usage
 /// of deserializable structs as body types in their handlers.
 ///
+/// Requests with `Content-Type` other than JSON are rejected with 415, bodies
+/// that are not valid JSON with 400 and the ones that don't match `T` with 422.
+///
+/// Returned from handler, serializable value is sent as JSON body.
+///
 /// ```rust
-/// use serde::Deserialize;
+/// use serde::{Deserialize, Serialize};
 /// use core::request::Json;
 ///
-/// #[derive(Deserialize)]
+/// #[derive(Deserialize, Serialize)]
 /// struct OwnBody {
 ///     val: String,
 ///     val2: i32
 /// }
 ///
-/// fn handler(Json(body): Json<OwnBody>) {}
+/// fn handler(Json(body): Json<OwnBody>) -> Json<OwnBody> {
+///     Json(body)
+/// }
 /// ```
 pub struct Json<T>(pub T);
 
+/// Sends value with `Content-Type: application/json`,
+/// failed serialization is an unhandled error.
+impl<T> Responder for Json<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> anyhow::Result<Response> {
+        let body = serde_json::to_vec(&self.0)?;
+        Ok(hyper::Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))?)
+    }
+}
+
 impl<S, T> FromRequest<Body, S> for Json<T>
 where
     T: DeserializeOwned,
 {
-    fn from_request(req: Request<Body>, _state: &S) -> anyhow::Result<Self> {
-        let bytes: Bytes = futures_executor::block_on(hyper::body::to_bytes(req.into_body()))?;
-        let deserializer = &mut serde_json::Deserializer::from_slice(&bytes);
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_json_content_type(req.headers())?;
+
+        let bytes = futures_executor::block_on(read_body(req))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl<S, T> FromRequestAsync<Body, S> for Json<T>
+where
+    S: Sync,
+    T: DeserializeOwned + Send,
+{
+    async fn from_request_async(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_json_content_type(req.headers())?;
+
+        let bytes = read_body(req).await?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Body without `Content-Type` is accepted, otherwise it has to be
+/// `application/json` or some `+json` type.
+fn check_json_content_type(headers: &HeaderMap) -> Result<(), Rejection> {
+    check_content_type(headers, "JSON", |mime| {
+        mime == "application/json" || mime.ends_with("+json")
+    })
+}
+
+/// Checks media type of the body, i.e. `Content-Type` without parameters,
+/// rejects request with 415 when it doesn't match. Missing `Content-Type`
+/// is accepted, it's up to the body's parser to decide if it's valid.
+pub(crate) fn check_content_type<F>(
+    headers: &HeaderMap,
+    expected: &str,
+    matches: F,
+) -> Result<(), Rejection>
+where
+    F: Fn(&str) -> bool,
+{
+    let content_type = match headers.get(CONTENT_TYPE) {
+        Some(value) => value.to_str().unwrap_or_default(),
+        None => return Ok(()),
+    };
 
-        let value = T::deserialize(deserializer)?;
-        Ok(Json(value))
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if matches(&mime) {
+        return Ok(());
     }
+
+    Err(Rejection::unsupported_media_type(format!(
+        "expected {} body, got {}",
+        expected, content_type
+    )))
 }
 
-/// Trait is implemented for types that can be turned from HeaderMap by specific key.
+impl<T> Json<T>
+where
+    T: DeserializeOwned,
+{
+    /// Deserializes value from raw JSON bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Rejection> {
+        let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+
+        match T::deserialize(deserializer) {
+            Ok(value) => Ok(Json(value)),
+            Err(e) if e.is_data() => Err(Rejection::unprocessable_entity(format!(
+                "invalid JSON body: {}",
+                e
+            ))),
+            Err(e) => Err(Rejection::bad_request(format!(
+                "malformed JSON body: {}",
+                e
+            ))),
+        }
+    }
+}
+
+/// Value deserialized from `application/x-www-form-urlencoded` body.
 ///
-/// Multiple, commonly used headers from hyper crate implements this trait.
-/// That allows to deserialize them straight into handler's param.
+/// Requests with other `Content-Type` are rejected with 415,
+/// bodies that don't match `T` with 422.
 ///
 /// ```rust
-/// use core::request::ContentType;
+/// use serde::Deserialize;
+/// use core::request::Form;
 ///
-/// fn handler_header(ContentType(content_type): ContentType) -> anyhow::Result<String> {
-///     Ok(content_type)
+/// #[derive(Deserialize)]
+/// struct Login {
+///     user: String,
+///     password: String,
 /// }
+///
+/// fn handler(Form(login): Form<Login>) {}
 /// ```
-pub trait TypedHeader: Sized {
-    /// Returns header's key.
-    fn key() -> HeaderName;
+pub struct Form<T>(pub T);
 
-    /// Tries to create Self from HeaderValue.
-    fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self>;
+impl<S, T> FromRequest<Body, S> for Form<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_form_content_type(req.headers())?;
 
-    /// Default implementation that uses `key` and `try_from_header_value` functions
-    /// to turn `map: HeaderMap<HeaderValue>` into `anyhow::Result<Self>`.
-    fn try_from_header_map(map: &HeaderMap<HeaderValue>) -> anyhow::Result<Self> {
-        Self::try_from_header_value(map.get(Self::key()).context("header not found")?)
+        let bytes = futures_executor::block_on(read_body(req))?;
+        Self::from_bytes(&bytes)
     }
 }
 
-/// Macro for faster TypedHeaderTrait implementations.
-macro_rules! derive_header {
-    ($type:ident(_), name: $name:ident) => {
-        impl TypedHeader for $type {
-            fn key() -> HeaderName {
-                $name
-            }
+impl<S, T> FromRequestAsync<Body, S> for Form<T>
+where
+    S: Sync,
+    T: DeserializeOwned + Send,
+{
+    async fn from_request_async(req: Request<Body>, _state: &S) -> Result<Self, Rejection> {
+        check_form_content_type(req.headers())?;
 
-            fn try_from_header_value(header_value: &HeaderValue) -> anyhow::Result<Self> {
-                Ok($type(header_value.to_str()?.to_string()))
-            }
-        }
-    };
+        let bytes = read_body(req).await?;
+        Self::from_bytes(&bytes)
+    }
 }
 
-// TODO: implement more headers.
-pub struct ContentType(pub String);
-derive_header!(ContentType(_), name: CONTENT_TYPE);
+fn check_form_content_type(headers: &HeaderMap) -> Result<(), Rejection> {
+    check_content_type(headers, "form", |mime| {
+        mime == "application/x-www-form-urlencoded"
+    })
+}
 
-pub struct Host(pub String);
-derive_header!(Host(_), name: HOST);
+impl<T> Form<T>
+where
+    T: DeserializeOwned,
+{
+    /// Deserializes value from urlencoded bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Rejection> {
+        serde_urlencoded::from_bytes(bytes)
+            .map(Form)
+            .map_err(|e| Rejection::unprocessable_entity(format!("invalid form body: {}", e)))
+    }
+}
 
 /// Types that implements this trait can be created from request's parts.
 /// This trait shouldn't be used directly, rather than that use some of its
-/// implementations like TypedHeader or PathParam.
+/// implementations like headers::TypedHeader or PathParam.
 pub trait FromRequestParts<S>: Sized {
-    fn from_request_parts(parts: &mut Parts, state: &S) -> anyhow::Result<Self>;
+    fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Rejection>;
+}
+
+/// Failing extractor gives `None` instead of rejecting the request.
+impl<S, T> FromRequestParts<S> for Option<T>
+where
+    T: FromRequestParts<S>,
+{
+    fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request_parts(parts, state).ok())
+    }
 }
 
-/// Implement FromRequestParts<S> for every type that implements TypedHeader trait.  
+/// Rejection of failing extractor is passed to the handler.
+impl<S, T> FromRequestParts<S> for Result<T, Rejection>
+where
+    T: FromRequestParts<S>,
+{
+    fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Rejection> {
+        Ok(T::from_request_parts(parts, state))
+    }
+}
+
+/// Implement FromRequestParts<S> for every type that implements Header trait.
 impl<S, T> FromRequestParts<S> for T
 where
-    T: TypedHeader,
+    T: Header,
 {
-    fn from_request_parts(parts: &mut Parts, _state: &S) -> anyhow::Result<Self> {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
         T::try_from_header_map(&parts.headers)
     }
 }
@@ -147,12 +495,25 @@ impl<S, T, B> FromRequest<B, S, private::ViaParts> for T
 where
     T: FromRequestParts<S>,
 {
-    fn from_request(req: Request<B>, state: &S) -> anyhow::Result<Self> {
+    fn from_request(req: Request<B>, state: &S) -> Result<Self, Rejection> {
         let (mut b, _) = req.into_parts();
         T::from_request_parts(&mut b, state)
     }
 }
 
+impl<S, T, B> FromRequestAsync<B, S, private::ViaParts> for T
+where
+    T: FromRequestParts<S> + Send,
+{
+    fn from_request_async(
+        req: Request<B>,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Rejection>> + Send {
+        let (mut b, _) = req.into_parts();
+        std::future::ready(T::from_request_parts(&mut b, state))
+    }
+}
+
 /// PathParamOrdering wrapper type for storing state of what params were already read.
 #[derive(Default, Clone, Copy)]
 struct PathParamOrdering(usize);
@@ -163,9 +524,19 @@ impl PathParamOrdering {
     }
 }
 
-/// Container for values that are retrieved from query params.
+/// Returns percent-decoded params of the matched route.
+fn path_params(parts: &Parts) -> Result<Vec<(String, String)>, Rejection> {
+    parts
+        .extensions
+        .get::<UrlParams>()
+        .ok_or_else(|| Rejection::internal("no path params provided"))?
+        .decode()
+}
+
+/// Container for a single path param value. Every `PathParam` in handler's
+/// arguments takes the next param of the path, in order of appearance.
 /// If inner type implements FromStr trait this container can be used
-/// in handler to get direct access for query param value.
+/// in handler to get direct access for path param value.
 ///
 /// ```
 /// use core::request::PathParam;
@@ -184,31 +555,22 @@ where
     T: FromStr,
     <T as FromStr>::Err: std::error::Error + Sync + Send,
 {
-    fn from_request_parts(parts: &mut Parts, _state: &S) -> anyhow::Result<Self> {
-        let path = parts.uri.to_string();
-
-        let segments = parts
-            .extensions
-            .get::<HashMap<usize, usize>>()
-            .context("no segments provided")?
-            .clone();
-
-        let binding = PathParamOrdering(0);
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
         let ordering = parts
             .extensions
             .get::<PathParamOrdering>()
-            .unwrap_or(&binding);
+            .copied()
+            .unwrap_or_default();
 
-        let order_in_path = segments
-            .get(&ordering.0)
-            .context("no value for wanted ordering")?;
+        let (_, value) = path_params(parts)?
+            .into_iter()
+            .nth(ordering.0)
+            .ok_or_else(|| Rejection::internal("no value for wanted ordering"))?;
 
-        let value_to_parse = path
-            .split('/')
-            .nth(*order_in_path + 1) // +1 because we have to skip first '/' as path starts with that.
-            .context("invalid value from a string")?;
-
-        let parsed = PathParam(T::from_str(value_to_parse)?);
+        let parsed =
+            PathParam(T::from_str(&value).map_err(|e| {
+                Rejection::bad_request(format!("invalid path param {}: {}", value, e))
+            })?);
 
         parts.extensions.insert(ordering.increment());
 
@@ -216,6 +578,82 @@ where
     }
 }
 
+/// Path params deserialized with [`serde::Deserialize`]. Structs are filled
+/// by params' names, tuples by their order and other types, like `u32`
+/// or `String`, by the only param of the route. Values are percent-decoded.
+///
+/// ```
+/// use core::request::Path;
+/// use core::route::Router;
+/// use crate::core::handler::HandlerTraitWithoutState;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Comment {
+///     post: u32,
+///     id: u32,
+/// }
+///
+/// fn user(Path(name): Path<String>) {}
+/// fn post(Path((user, post)): Path<(String, u32)>) {}
+/// fn comment(Path(comment): Path<Comment>) {}
+///
+/// Router::default()
+///     .get("/users/<name>", user)
+///     .get("/users/<name>/posts/<post>", post)
+///     .get("/posts/<post>/comments/<id>", comment);
+/// ```
+pub struct Path<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        let params = path_params(parts)?;
+
+        T::deserialize(PathDeserializer::new(&params))
+            .map(Path)
+            .map_err(|e| Rejection::bad_request(format!("invalid path params: {}", e)))
+    }
+}
+
+/// Names of matched route's params with their percent-decoded values,
+/// in order of appearance in the path.
+///
+/// ```
+/// use core::request::RawPathParams;
+///
+/// // /users/<name>
+/// fn handler(params: RawPathParams) -> String {
+///     params.get("name").unwrap_or_default().to_string()
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RawPathParams(Vec<(String, String)>);
+
+impl RawPathParams {
+    /// Returns value of the param with given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Iterates over params' names and values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl<S> FromRequestParts<S> for RawPathParams {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        path_params(parts).map(RawPathParams)
+    }
+}
+
 /// Container for query value retrieved from an url.
 ///
 /// ```rust
@@ -246,10 +684,16 @@ impl<S, T> FromRequestParts<S> for Query<T>
 where
     T: DeserializeOwned,
 {
-    fn from_request_parts(parts: &mut Parts, _state: &S) -> anyhow::Result<Self> {
-        Ok(Query(serde_urlencoded::from_str(
-            parts.uri.query().context("not queries provided")?,
-        )?))
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        let query = parts
+            .uri
+            .query()
+            .ok_or_else(|| Rejection::bad_request("not queries provided"))?;
+
+        match serde_urlencoded::from_str(query) {
+            Ok(value) => Ok(Query(value)),
+            Err(e) => Err(Rejection::bad_request(format!("invalid query: {}", e))),
+        }
     }
 }
 
@@ -261,18 +705,61 @@ where
 /// fn handler(headers: HeaderMap) {}
 /// ```
 impl<S> FromRequestParts<S> for HeaderMap {
-    fn from_request_parts(parts: &mut Parts, _state: &S) -> anyhow::Result<Self> {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
         Ok(parts.headers.clone())
     }
 }
 
+/// Router's state, or a part of it, given to the handler. `T` has to implement
+/// `FromRef<S>` for router's state `S`, which is true for `S` itself as long as
+/// it's `Clone`. State is cloned for every request, so types that are expensive
+/// to clone should be kept in `Arc`.
+///
+/// ```rust
+/// use core::request::{FromRef, State};
+/// use core::route::Router;
+/// use std::sync::Arc;
+///
+/// #[derive(Clone, FromRef)]
+/// struct AppState {
+///     db: Arc<String>,
+///     config: Arc<Vec<String>>,
+/// }
+///
+/// fn handler(State(db): State<Arc<String>>) -> String {
+///     db.to_string()
+/// }
+///
+/// let state = AppState {
+///     db: Arc::new("postgres://".into()),
+///     config: Arc::new(vec![]),
+/// };
+/// let app = Router::with_state(state).get("/", handler);
+/// ```
 pub struct State<T>(pub T);
 
-impl<S> FromRequestParts<S> for State<S>
+impl<S, T> FromRequestParts<S> for State<T>
+where
+    T: FromRef<S>,
+{
+    fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Rejection> {
+        Ok(State(T::from_ref(state)))
+    }
+}
+
+/// Creates value out of a reference to router's state, used by `State` extractor.
+/// Can be derived for a struct with `#[derive(FromRef)]`, which implements it
+/// for types of all struct's fields. Fields marked with `#[from_ref(skip)]`
+/// are left out, e.g. when two of them have the same type.
+pub trait FromRef<T> {
+    fn from_ref(input: &T) -> Self;
+}
+
+impl<T> FromRef<T> for T
 where
-    S: Clone,
+    T: Clone,
 {
-    fn from_request_parts(_parts: &mut Parts, state: &S) -> anyhow::Result<Self> {
-        Ok(State(state.clone()))
+    fn from_ref(input: &T) -> Self {
+        input.clone()
     }
 }
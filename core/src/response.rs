@@ -1,5 +1,15 @@
+use anyhow::anyhow;
 use bytes::{BufMut, Bytes, BytesMut};
-use hyper::{Body, StatusCode};
+use hyper::{
+    body::HttpBody,
+    header::{
+        HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, DATE, LOCATION, SERVER,
+        TRANSFER_ENCODING,
+    },
+    http::response::Parts,
+    Body, HeaderMap, Method, Request, StatusCode, Version,
+};
+use std::{future::Future, io::Write, time::SystemTime};
 
 pub type Response = hyper::Response<Body>;
 
@@ -7,43 +17,293 @@ pub trait Responder {
     fn into_response(self) -> anyhow::Result<Response>;
 }
 
+/// Asynchronous counterpart of `Responder`, returned values of `async fn` handlers
+/// have to implement it. Every `Responder` implements it as well, types that need
+/// to await something before the response is ready can implement it directly.
+pub trait AsyncResponder {
+    fn into_response_async(self) -> impl Future<Output = anyhow::Result<Response>> + Send;
+}
+
+impl<T> AsyncResponder for T
+where
+    T: Responder,
+{
+    fn into_response_async(self) -> impl Future<Output = anyhow::Result<Response>> + Send {
+        std::future::ready(self.into_response())
+    }
+}
+
 pub fn body_to_bytes(body: Body) -> anyhow::Result<Bytes> {
     let body_bytes = futures_executor::block_on(hyper::body::to_bytes(body))?;
     Ok(body_bytes)
 }
 
+/// Value of `Server` header added to every response.
+const SERVER_NAME: &str = "rhttp";
+
+/// Parts of the request that decide how response to it is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHead {
+    pub method: Method,
+    pub version: Version,
+}
+
+impl RequestHead {
+    pub fn of<B>(request: &Request<B>) -> Self {
+        Self {
+            method: request.method().clone(),
+            version: request.version(),
+        }
+    }
+}
+
+impl Default for RequestHead {
+    fn default() -> Self {
+        Self {
+            method: Method::GET,
+            version: Version::HTTP_11,
+        }
+    }
+}
+
+/// Serializes whole response into HTTP/1.1 message.
 pub fn response_to_bytes(response: Response) -> anyhow::Result<Vec<u8>> {
-    use std::fmt::Write as _; // import without risk of name clashing
+    let mut buffer = Vec::with_capacity(1024 * 8); // 8kB
+    write_response(response, &RequestHead::default(), &mut buffer)?;
+    Ok(buffer)
+}
 
-    let mut buffer = BytesMut::with_capacity(1024 * 8); // 8kB
-    let status = response.status();
-    let (status_code, status_message) = (status.as_u16(), status.as_str());
+/// Writes response to the request as HTTP/1.1 message. `Content-Length`, `Date`
+/// and `Server` headers are added if missing. Body with unknown length is sent
+/// with chunked transfer encoding, chunk by chunk as it is produced. HTTP/1.0
+/// clients can't decode it, their body ends when connection is closed instead,
+/// see `is_close_delimited`. Response to HEAD request is written without the body.
+pub fn write_response<W>(
+    response: Response,
+    head: &RequestHead,
+    writer: &mut W,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let (mut parts, mut body) = response.into_parts();
+    let framing = skip_head_body(prepare_headers(&mut parts, &body, head), head);
+    let mut buffer = encode_head(&parts);
 
+    match framing {
+        BodyFraming::None => writer.write_all(&buffer)?,
+        BodyFraming::Length => {
+            buffer.put(body_to_bytes(body)?);
+            writer.write_all(&buffer)?;
+        }
+        BodyFraming::Chunked | BodyFraming::Close => {
+            writer.write_all(&buffer)?;
+            while let Some(chunk) = futures_executor::block_on(body.data()) {
+                let chunk = chunk?;
+                if chunk.is_empty() {
+                    continue;
+                }
+                match framing {
+                    BodyFraming::Chunked => writer.write_all(&encode_chunk(chunk))?,
+                    _ => writer.write_all(&chunk)?,
+                }
+            }
+            if let BodyFraming::Chunked = framing {
+                writer.write_all(LAST_CHUNK)?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Asynchronous version of `write_response`, body is polled without blocking.
+#[cfg(feature = "tokio")]
+pub async fn write_response_async<W>(
+    response: Response,
+    head: &RequestHead,
+    writer: &mut W,
+) -> anyhow::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let (mut parts, mut body) = response.into_parts();
+    let framing = skip_head_body(prepare_headers(&mut parts, &body, head), head);
+    let mut buffer = encode_head(&parts);
+
+    match framing {
+        BodyFraming::None => writer.write_all(&buffer).await?,
+        BodyFraming::Length => {
+            buffer.put(hyper::body::to_bytes(body).await?);
+            writer.write_all(&buffer).await?;
+        }
+        BodyFraming::Chunked | BodyFraming::Close => {
+            writer.write_all(&buffer).await?;
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk?;
+                if chunk.is_empty() {
+                    continue;
+                }
+                match framing {
+                    BodyFraming::Chunked => writer.write_all(&encode_chunk(chunk)).await?,
+                    _ => writer.write_all(&chunk).await?,
+                }
+            }
+            if let BodyFraming::Chunked = framing {
+                writer.write_all(LAST_CHUNK).await?;
+            }
+        }
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Encodes status line and headers.
+fn encode_head(parts: &Parts) -> BytesMut {
+    use std::fmt::Write as _; // import without risk of name clashing
+
+    let mut buffer = BytesMut::with_capacity(1024);
     let _ = write!(
         &mut buffer,
-        "{:?} {} {}",
-        response.version(),
-        status_code,
-        status_message
+        "{:?} {} {}\r\n",
+        parts.version,
+        parts.status.as_u16(),
+        parts.status.canonical_reason().unwrap_or_default()
     );
 
-    buffer.write_char('\n')?;
-
-    for (k, v) in response.headers() {
-        let _ = writeln!(&mut buffer, "{}: ", k);
+    for (k, v) in &parts.headers {
+        buffer.put(k.as_str().as_bytes());
+        buffer.put(&b": "[..]);
         buffer.put(v.as_bytes());
-        buffer.write_char('\n')?;
+        buffer.put(&b"\r\n"[..]);
+    }
+    buffer.put(&b"\r\n"[..]);
+    buffer
+}
+
+/// Chunk of size 0 that ends chunked body, without trailer fields.
+const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+/// Encodes single chunk of chunked body.
+fn encode_chunk(chunk: Bytes) -> BytesMut {
+    use std::fmt::Write as _;
+
+    let mut buffer = BytesMut::with_capacity(chunk.len() + 12);
+    let _ = write!(&mut buffer, "{:X}\r\n", chunk.len());
+    buffer.put(chunk);
+    buffer.put(&b"\r\n"[..]);
+    buffer
+}
+
+/// Describes how response's body is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFraming {
+    /// Response can't have a body.
+    None,
+
+    /// Whole body is sent at once, delimited by `Content-Length`.
+    Length,
+
+    /// Body is sent with chunked transfer encoding.
+    Chunked,
+
+    /// Body is sent as it is produced and ends when connection is closed.
+    Close,
+}
+
+/// Indicates if response's body can be delimited only by closing the connection,
+/// i.e. its length is unknown and client does not understand chunked encoding.
+pub fn is_close_delimited(response: &Response, head: &RequestHead) -> bool {
+    head.method != Method::HEAD
+        && body_framing(response.status(), response.headers(), response.body(), head)
+            == BodyFraming::Close
+}
+
+/// Response to HEAD request has headers describing the body, but the body
+/// itself is never sent.
+fn skip_head_body(framing: BodyFraming, head: &RequestHead) -> BodyFraming {
+    match head.method {
+        Method::HEAD => BodyFraming::None,
+        _ => framing,
     }
+}
 
-    let body_bytes = body_to_bytes(response.into_body())?;
-    if body_bytes.is_empty() {
-        return Ok(buffer.to_vec());
+/// Decides how response's body is sent.
+fn body_framing(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &Body,
+    head: &RequestHead,
+) -> BodyFraming {
+    // 1xx, 204 and 304 responses are never followed by a body.
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        return BodyFraming::None;
     }
 
-    buffer.write_str("\n\n")?;
-    buffer.put(body_bytes);
+    let chunked = headers
+        .get(TRANSFER_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase().contains("chunked"))
+        .unwrap_or_default();
+    if (headers.contains_key(CONTENT_LENGTH) || body.size_hint().exact().is_some()) && !chunked {
+        return BodyFraming::Length;
+    }
+
+    match head.version >= Version::HTTP_11 {
+        true => BodyFraming::Chunked,
+        false => BodyFraming::Close,
+    }
+}
+
+/// Fills in headers that are required by the protocol and decides how body is sent.
+fn prepare_headers(parts: &mut Parts, body: &Body, head: &RequestHead) -> BodyFraming {
+    let framing = body_framing(parts.status, &parts.headers, body, head);
+    let headers = &mut parts.headers;
+
+    if !headers.contains_key(DATE) {
+        if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(SystemTime::now())) {
+            headers.insert(DATE, date);
+        }
+    }
+    if !headers.contains_key(SERVER) {
+        headers.insert(SERVER, HeaderValue::from_static(SERVER_NAME));
+    }
 
-    Ok(buffer.to_vec())
+    match framing {
+        BodyFraming::None => {
+            headers.remove(TRANSFER_ENCODING);
+            if parts.status != StatusCode::NOT_MODIFIED {
+                headers.remove(CONTENT_LENGTH);
+            }
+        }
+        BodyFraming::Length => {
+            if let (false, Some(length)) = (
+                headers.contains_key(CONTENT_LENGTH),
+                body.size_hint().exact(),
+            ) {
+                headers.insert(CONTENT_LENGTH, length.into());
+            }
+        }
+        BodyFraming::Chunked => {
+            headers.remove(CONTENT_LENGTH);
+            headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        }
+        BodyFraming::Close => {
+            headers.remove(CONTENT_LENGTH);
+            headers.remove(TRANSFER_ENCODING);
+            if head.method != Method::HEAD {
+                headers.insert(CONNECTION, HeaderValue::from_static("close"));
+            }
+        }
+    }
+    framing
 }
 
 /// Responder implementation for '()', returns default Response (200, HTTP1.1).
@@ -84,7 +344,7 @@ impl Responder for Response {
 /// Router::default().get("/", handler);
 ///
 /// ```
-impl<'a> Responder for &'a str {
+impl Responder for &str {
     fn into_response(self) -> anyhow::Result<Response> {
         Ok(hyper::Response::builder().body(Body::from(self.to_string()))?)
     }
@@ -109,28 +369,427 @@ impl Responder for String {
     }
 }
 
-impl Responder for i32 {
+/// Numbers are sent as their text representation.
+macro_rules! implement_number_responder {
+    ($($ty:ty),*) => {
+        $(
+            impl Responder for $ty {
+                fn into_response(self) -> anyhow::Result<Response> {
+                    self.to_string().into_response()
+                }
+            }
+        )*
+    };
+}
+
+implement_number_responder!(i8, i16, i32, i64, i128, isize);
+implement_number_responder!(u8, u16, u32, u64, u128, usize);
+implement_number_responder!(f32, f64);
+
+impl Responder for bool {
     fn into_response(self) -> anyhow::Result<Response> {
         self.to_string().into_response()
     }
 }
 
-impl Responder for bool {
+/// Both variants are responded with their own `Responder` implementations,
+/// so handlers can return e.g. `anyhow::Result<T>` or `Result<T, HttpError>`.
+///
+/// ```rust
+/// use core::error::HttpError;
+/// use core::route::Router;
+///
+/// fn handler() -> Result<String, HttpError> {
+///     Err(HttpError::bad_request("try again"))
+/// }
+///
+/// Router::default().get("/", handler);
+/// ```
+impl<T, E> Responder for Result<T, E>
+where
+    T: Responder,
+    E: Responder,
+{
     fn into_response(self) -> anyhow::Result<Response> {
-        self.to_string().into_response()
+        match self {
+            Ok(r) => r.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
+
+/// Raw bytes are sent with `Content-Type: application/octet-stream`.
+impl Responder for Bytes {
+    fn into_response(self) -> anyhow::Result<Response> {
+        Ok(hyper::Response::builder()
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(self))?)
+    }
+}
+
+impl Responder for Vec<u8> {
+    fn into_response(self) -> anyhow::Result<Response> {
+        Bytes::from(self).into_response()
+    }
+}
+
+/// Empty response with the status.
+///
+/// ```rust
+/// use core::route::Router;
+/// use hyper::StatusCode;
+///
+/// fn handler() -> StatusCode {
+///     StatusCode::NO_CONTENT
+/// }
+///
+/// Router::default().delete("/", handler);
+/// ```
+impl Responder for StatusCode {
+    fn into_response(self) -> anyhow::Result<Response> {
+        let mut response = Response::default();
+        *response.status_mut() = self;
+        Ok(response)
     }
 }
 
-impl<T> Responder for anyhow::Result<T>
+/// `Some` is responded with its own `Responder`, `None` with empty 404.
+///
+/// ```rust
+/// use core::request::PathParam;
+/// use core::route::Router;
+///
+/// fn user(PathParam(id): PathParam<u32>) -> Option<String> {
+///     (id == 1).then(|| "john".to_string())
+/// }
+///
+/// Router::default().get("/users/<id>", user);
+/// ```
+impl<T> Responder for Option<T>
 where
     T: Responder,
 {
     fn into_response(self) -> anyhow::Result<Response> {
         match self {
-            Ok(r) => Ok(r.into_response()?),
-            Err(e) => Ok(hyper::Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::from(e.to_string()))?),
+            Some(r) => r.into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
+/// Redirects client to other location with `Location` header.
+///
+/// ```rust
+/// use core::response::Redirect;
+/// use core::route::Router;
+///
+/// fn old() -> Redirect {
+///     Redirect::permanent("/new")
+/// }
+///
+/// fn login() -> Redirect {
+///     Redirect::see_other("/dashboard")
+/// }
+///
+/// Router::default().get("/old", old).post("/login", login);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirect {
+    /// Temporary redirect with 307, request's method and body are kept.
+    pub fn to(location: impl Into<String>) -> Self {
+        Self::new(StatusCode::TEMPORARY_REDIRECT, location)
+    }
+
+    /// Permanent redirect with 308, request's method and body are kept.
+    pub fn permanent(location: impl Into<String>) -> Self {
+        Self::new(StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    /// Redirect with 303, client follows it with GET, e.g. after form was posted.
+    pub fn see_other(location: impl Into<String>) -> Self {
+        Self::new(StatusCode::SEE_OTHER, location)
+    }
+
+    fn new(status: StatusCode, location: impl Into<String>) -> Self {
+        Self {
+            status,
+            location: location.into(),
+        }
+    }
+}
+
+/// Location that is not valid header's value is an unhandled error.
+impl Responder for Redirect {
+    fn into_response(self) -> anyhow::Result<Response> {
+        let location = HeaderValue::from_str(&self.location)
+            .map_err(|e| anyhow!("invalid redirect location {:?}: {}", self.location, e))?;
+
+        let mut response = self.status.into_response()?;
+        response.headers_mut().insert(LOCATION, location);
+        Ok(response)
+    }
+}
+
+/// Values that add something to response of another responder, e.g. headers.
+/// Handler returns them together with the responder as `(parts, responder)`.
+pub trait ResponseParts {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()>;
+}
+
+/// Replaces response's status.
+impl ResponseParts for StatusCode {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        *response.status_mut() = self;
+        Ok(())
+    }
+}
+
+/// Replaces response's headers with the same names.
+impl ResponseParts for HeaderMap {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        response.headers_mut().extend(self);
+        Ok(())
+    }
+}
+
+/// Replaces response's headers with the same names, later ones win.
+impl<const N: usize> ResponseParts for [(HeaderName, HeaderValue); N] {
+    fn extend_response(self, response: &mut Response) -> anyhow::Result<()> {
+        for (name, value) in self {
+            response.headers_mut().insert(name, value);
+        }
+        Ok(())
+    }
+}
+
+/// Responds with the responder, the last element, and extends its response
+/// with the parts, in order.
+///
+/// ```rust
+/// use core::headers::{TypedHeader, UserAgent};
+/// use core::request::Json;
+/// use core::route::Router;
+/// use hyper::header::{HeaderValue, CACHE_CONTROL, LOCATION};
+/// use hyper::{HeaderMap, StatusCode};
+///
+/// fn agent() -> (TypedHeader<UserAgent>, &'static str) {
+///     (TypedHeader(UserAgent("rhttp".into())), "hello")
+/// }
+///
+/// fn create() -> (StatusCode, [(hyper::header::HeaderName, HeaderValue); 1], Json<u32>) {
+///     let location = [(LOCATION, HeaderValue::from_static("/items/1"))];
+///     (StatusCode::CREATED, location, Json(1))
+/// }
+///
+/// fn cached() -> (StatusCode, HeaderMap, String) {
+///     let mut headers = HeaderMap::new();
+///     headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=60"));
+///     (StatusCode::OK, headers, "cached".into())
+/// }
+///
+/// Router::default()
+///     .get("/agent", agent)
+///     .post("/items", create)
+///     .get("/cached", cached);
+/// ```
+macro_rules! implement_tuple_responder {
+    ($($part:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($part,)* R> Responder for ($($part,)* R)
+        where
+            $($part: ResponseParts,)*
+            R: Responder,
+        {
+            fn into_response(self) -> anyhow::Result<Response> {
+                let ($($part,)* responder) = self;
+                let mut response = responder.into_response()?;
+                $(
+                    $part.extend_response(&mut response)?;
+                )*
+                Ok(response)
+            }
         }
+    };
+}
+
+implement_tuple_responder!(P1);
+implement_tuple_responder!(P1, P2);
+implement_tuple_responder!(P1, P2, P3);
+implement_tuple_responder!(P1, P2, P3, P4);
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_close_delimited, response_to_bytes, write_response, Redirect, RequestHead, Responder,
+    };
+    use bytes::Bytes;
+    use hyper::{
+        header::{HeaderName, HeaderValue, CONTENT_TYPE, ETAG, LOCATION},
+        Body, HeaderMap, Method, Response, StatusCode, Version,
+    };
+
+    #[test]
+    fn test_response_with_length() {
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("x-custom", "value")
+            .body(Body::from("hello"))
+            .unwrap();
+
+        let raw = String::from_utf8(response_to_bytes(response).unwrap()).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(raw.contains("x-custom: value\r\n"));
+        assert!(raw.contains("content-length: 5\r\n"));
+        assert!(raw.contains("server: rhttp\r\n"));
+        assert!(raw.contains("date: "));
+        assert!(raw.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_response_chunked() {
+        let (mut sender, body) = Body::channel();
+        let producer = std::thread::spawn(move || {
+            futures_executor::block_on(async {
+                sender.send_data(Bytes::from("hello ")).await.unwrap();
+                sender.send_data(Bytes::from("world")).await.unwrap();
+            })
+        });
+
+        let raw = String::from_utf8(response_to_bytes(Response::new(body)).unwrap()).unwrap();
+        producer.join().unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(raw.contains("transfer-encoding: chunked\r\n"));
+        assert!(!raw.contains("content-length"));
+        assert!(raw.ends_with("\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_response_close_delimited() {
+        let (mut sender, body) = Body::channel();
+        let producer = std::thread::spawn(move || {
+            futures_executor::block_on(async {
+                sender.send_data(Bytes::from("hello ")).await.unwrap();
+                sender.send_data(Bytes::from("world")).await.unwrap();
+            })
+        });
+
+        // HTTP/1.0 clients don't understand chunked encoding.
+        let head = RequestHead {
+            method: Method::GET,
+            version: Version::HTTP_10,
+        };
+        let response = Response::new(body);
+        assert!(is_close_delimited(&response, &head));
+        assert!(!is_close_delimited(&response, &RequestHead::default()));
+
+        let mut raw = vec![];
+        write_response(response, &head, &mut raw).unwrap();
+        producer.join().unwrap();
+
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.contains("connection: close\r\n"));
+        assert!(!raw.contains("transfer-encoding"));
+        assert!(!raw.contains("content-length"));
+        assert!(raw.ends_with("\r\n\r\nhello world"));
+
+        let response = Response::new(Body::from("known"));
+        assert!(!is_close_delimited(&response, &head));
+    }
+
+    #[test]
+    fn test_response_to_head() {
+        let head = RequestHead {
+            method: Method::HEAD,
+            version: Version::HTTP_11,
+        };
+
+        let mut raw = vec![];
+        write_response(Response::new(Body::from("hello")), &head, &mut raw).unwrap();
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.contains("content-length: 5\r\n"));
+        assert!(raw.ends_with("\r\n\r\n"));
+
+        // Length of streamed body is unknown, it's not described as empty.
+        let (_sender, body) = Body::channel();
+        let mut raw = vec![];
+        write_response(Response::new(body), &head, &mut raw).unwrap();
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(!raw.contains("content-length"));
+        assert!(raw.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_response_without_body() {
+        let response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::from("ignored"))
+            .unwrap();
+
+        let raw = String::from_utf8(response_to_bytes(response).unwrap()).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(!raw.contains("content-length"));
+        assert!(raw.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_status_and_tuple_responders() {
+        let response = StatusCode::NO_CONTENT.into_response().unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = (StatusCode::CREATED, "created").into_response().unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(body_of(response), "created");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let response = (StatusCode::ACCEPTED, headers, 42u64)
+            .into_response()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers()[ETAG], "\"v1\"");
+        assert_eq!(body_of(response), "42");
+
+        let headers: [(HeaderName, HeaderValue); 1] =
+            [(CONTENT_TYPE, HeaderValue::from_static("text/plain"))];
+        let response = (headers, 1.5f64).into_response().unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(body_of(response), "1.5");
+    }
+
+    #[test]
+    fn test_option_and_bytes_responders() {
+        let response = None::<String>.into_response().unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = Some(vec![1u8, 2, 3]).into_response().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
+        assert_eq!(body_of(response), "\u{1}\u{2}\u{3}");
+    }
+
+    #[test]
+    fn test_redirect() {
+        let cases = [
+            (Redirect::to("/a"), StatusCode::TEMPORARY_REDIRECT),
+            (Redirect::permanent("/a"), StatusCode::PERMANENT_REDIRECT),
+            (Redirect::see_other("/a"), StatusCode::SEE_OTHER),
+        ];
+        for (redirect, status) in cases {
+            let response = redirect.into_response().unwrap();
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()[LOCATION], "/a");
+        }
+
+        assert!(Redirect::to("/a\nb").into_response().is_err());
+    }
+
+    fn body_of(response: Response<Body>) -> String {
+        let body = super::body_to_bytes(response.into_body()).unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
     }
 }
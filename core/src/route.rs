@@ -1,49 +1,387 @@
 use crate::{
-    handler::{BoxCloneService, HandlerTrait, Service},
-    middleware::Middleware,
-    response::Response,
+    body::BodyLimit,
+    error::{internal_error, UnhandledError},
+    handler::{BoxCloneService, BoxFuture, HandlerTrait, Service},
+    middleware::{AroundMiddleware, Next},
+    path::UrlParams,
+    response::{Responder, Response},
+    tree::{parse_path, Match, Segment, Tree},
+};
+use anyhow::anyhow;
+use hyper::{header::ALLOW, Body, Method, Request, StatusCode};
+use log::error;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    marker::PhantomData,
+    ops::BitOr,
+    sync::{Arc, OnceLock},
 };
-use anyhow::{bail, Context};
-use hyper::{Body, Method, Request};
-use std::{collections::HashMap, sync::Arc};
 
 /// Main entity that delegates all routing in an application.
 #[derive(Clone)]
 pub struct Router<S> {
-    state: Arc<S>,
-    routes: HashMap<Method, Vec<Route>>,
+    state: Arc<StateCell<S>>,
+
+    /// Routes of each method, see `tree::Tree` for matching rules.
+    routes: HashMap<Method, Tree<Route>>,
 
     /// Registered middlewares that will be run during request handling.
     /// These are global middlewares, note that each route can have
     /// its own middleware so we can have different behaviors based on route.
-    middlewares: Vec<Box<dyn Middleware>>,
+    ///
+    /// Global middlewares run for every request, also when no route matches.
+    /// They wrap group's middlewares, which wrap route's ones. Middlewares
+    /// registered earlier wrap the ones registered later.
+    middlewares: Vec<Arc<dyn AroundMiddleware>>,
+
+    /// Handler called when no route matches request, see `Router::fallback`.
+    fallback: Option<Arc<BoxCloneService<Request<Body>>>>,
+
+    /// Renders unhandled errors, see `Router::error_handler`.
+    error_handler: Option<Arc<ErrorHandler>>,
+
+    /// Limit of bodies read by extractors, see `Router::body_limit`.
+    body_limit: Option<BodyLimit>,
+}
+
+type ErrorHandler = dyn Fn(anyhow::Error) -> Response + Send + Sync;
+
+/// Router's state, shared by its handlers. Router created with
+/// `Router::inherit_state` gets it from the router it's added to.
+struct StateCell<S>(OnceLock<StateRef<S>>);
+
+enum StateRef<S> {
+    Own(Arc<S>),
+    Inherited(Arc<StateCell<S>>),
+}
+
+impl<S> StateCell<S> {
+    fn get(&self) -> Option<&S> {
+        match self.0.get()? {
+            StateRef::Own(state) => Some(state),
+            StateRef::Inherited(parent) => parent.get(),
+        }
+    }
+}
+
+/// Handler registered in router, called with router's state.
+struct WithState<H, S, Q> {
+    handler: H,
+    state: Arc<StateCell<S>>,
+    _marker: PhantomData<fn() -> Q>,
 }
 
-impl Router<()> {
-    pub fn default() -> Self {
+impl<H, S, Q> Service<Request<Body>> for WithState<H, S, Q>
+where
+    H: HandlerTrait<Q, S>,
+{
+    fn call(&self, req: Request<Body>) -> Response {
+        match self.state.get() {
+            Some(state) => self.handler.handle(req, state),
+            None => internal_error(missing_state()),
+        }
+    }
+
+    fn call_async<'a>(&'a self, req: Request<Body>) -> BoxFuture<'a, Response> {
+        match self.state.get() {
+            Some(state) => self.handler.handle_async(req, state),
+            None => Box::pin(std::future::ready(internal_error(missing_state()))),
+        }
+    }
+
+    fn is_blocking(&self, req: &Request<Body>) -> bool {
+        self.handler.is_blocking(req)
+    }
+}
+
+fn missing_state() -> anyhow::Error {
+    anyhow!("router created with Router::inherit_state was not added to other router")
+}
+
+/// Methods registered for request's path, passed in request's extensions
+/// to the fallback handler. Empty when path is not registered at all.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedMethods(pub Vec<Method>);
+
+impl Default for Router<()> {
+    fn default() -> Self {
         Self::with_state(())
     }
 }
 
+/// Where request is routed to.
+enum Target<'t, 'p> {
+    Route(Match<'t, 'p, Route>),
+    /// OPTIONS request answered with `Allow` header of the methods.
+    Options(Vec<Method>),
+    NotFound,
+}
+
 impl<S> Router<S> {
-    fn call(&self, mut request: Request<Body>) -> anyhow::Result<Response> {
-        let route = self
+    fn call(&self, request: Request<Body>) -> anyhow::Result<Response> {
+        let path = request.uri().path().to_string();
+
+        match self.route(request.method(), &path) {
+            Target::Route(matched) => fire(matched, request),
+            Target::Options(allowed) => Ok(options_response(&allowed)),
+            Target::NotFound => Ok(self.not_found(request)),
+        }
+    }
+
+    /// Asynchronous version of `call`.
+    async fn call_routed_async(&self, request: Request<Body>) -> anyhow::Result<Response> {
+        let path = request.uri().path().to_string();
+
+        match self.route(request.method(), &path) {
+            Target::Route(matched) => fire_async(matched, request).await,
+            Target::Options(allowed) => Ok(options_response(&allowed)),
+            Target::NotFound => Ok(self.not_found_async(request).await),
+        }
+    }
+
+    fn route<'t, 'p>(&'t self, method: &Method, path: &'p str) -> Target<'t, 'p> {
+        if let Some(matched) = self.at(method, path) {
+            return Target::Route(matched);
+        }
+
+        match *method {
+            Method::HEAD => {
+                if let Some(matched) = self.at(&Method::GET, path) {
+                    return Target::Route(matched);
+                }
+            }
+            Method::OPTIONS => {
+                let allowed = self.allowed_methods(path);
+                if !allowed.is_empty() {
+                    return Target::Options(allowed);
+                }
+            }
+            _ => {}
+        }
+
+        Target::NotFound
+    }
+
+    fn at<'t, 'p>(&'t self, method: &Method, path: &'p str) -> Option<Match<'t, 'p, Route>> {
+        self.routes.get(method).and_then(|routes| routes.at(path))
+    }
+
+    /// Returns sorted methods registered for the path, including HEAD and OPTIONS
+    /// which are answered automatically.
+    fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut allowed: Vec<Method> = self
             .routes
-            .get(request.method())
-            .with_context(|| format!("not registered routes for {:?} method", request.method()))?
             .iter()
-            .find(|route| route.should_fire_on_path(request.uri().path()))
-            .context("no matching route")?;
+            .filter(|(_, routes)| routes.at(path).is_some())
+            .map(|(method, _)| method.clone())
+            .collect();
 
-        let extensions = request.extensions_mut();
-        extensions.insert(route.metadata.param_segments.clone());
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        if !allowed.is_empty() && !allowed.contains(&Method::OPTIONS) {
+            allowed.push(Method::OPTIONS);
+        }
+        allowed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        allowed
+    }
+
+    /// Answers request that no route matched. Path registered for other methods
+    /// gets 405 with `Allow` header, unknown one gets 404.
+    fn not_found(&self, mut request: Request<Body>) -> Response {
+        let allowed = self.allowed_methods(request.uri().path());
+
+        let response = match &self.fallback {
+            Some(fallback) => {
+                request
+                    .extensions_mut()
+                    .insert(AllowedMethods(allowed.clone()));
+                fallback.0.call(request)
+            }
+            None => not_found_response(&allowed),
+        };
+        with_allow_header(response, &allowed)
+    }
 
-        let response = route.fire(request)?;
+    /// Asynchronous version of `not_found`.
+    async fn not_found_async(&self, mut request: Request<Body>) -> Response {
+        let allowed = self.allowed_methods(request.uri().path());
 
-        Ok(response)
+        let response = match &self.fallback {
+            Some(fallback) => {
+                request
+                    .extensions_mut()
+                    .insert(AllowedMethods(allowed.clone()));
+                fallback.0.call_async(request).await
+            }
+            None => not_found_response(&allowed),
+        };
+        with_allow_header(response, &allowed)
+    }
+
+    /// Tells if request can't be answered without blocking the thread, see
+    /// `Service::is_blocking`. Route is found before middlewares are run, so
+    /// non-blocking middlewares shouldn't change request's method or path.
+    fn is_blocking(&self, request: &Request<Body>) -> bool {
+        if self.middlewares.iter().any(|m| m.is_blocking()) {
+            return true;
+        }
+
+        match self.route(request.method(), request.uri().path()) {
+            Target::Route(matched) => matched.value.is_blocking(request),
+            Target::Options(_) => false,
+            Target::NotFound => self
+                .fallback
+                .as_ref()
+                .is_some_and(|fallback| fallback.0.is_blocking(request)),
+        }
     }
 }
 
+/// Calls matched route with its params in request's extensions.
+fn fire(matched: Match<Route>, mut request: Request<Body>) -> anyhow::Result<Response> {
+    insert_params(&matched, &mut request);
+    matched.value.fire(request)
+}
+
+/// Asynchronous version of `fire`.
+async fn fire_async(
+    matched: Match<'_, '_, Route>,
+    mut request: Request<Body>,
+) -> anyhow::Result<Response> {
+    insert_params(&matched, &mut request);
+    matched.value.fire_async(request).await
+}
+
+fn insert_params(matched: &Match<Route>, request: &mut Request<Body>) {
+    let params = matched
+        .params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    request.extensions_mut().insert(UrlParams(params));
+}
+
+fn options_response(allowed: &[Method]) -> Response {
+    let mut response = Response::default();
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    set_allow_header(&mut response, allowed);
+    response
+}
+
+fn not_found_response(allowed: &[Method]) -> Response {
+    let mut response = Response::default();
+    *response.status_mut() = match allowed.is_empty() {
+        true => StatusCode::NOT_FOUND,
+        false => StatusCode::METHOD_NOT_ALLOWED,
+    };
+    response
+}
+
+/// Sets `Allow` header of 405 response, unless it's already there.
+fn with_allow_header(mut response: Response, allowed: &[Method]) -> Response {
+    if response.status() == StatusCode::METHOD_NOT_ALLOWED
+        && !response.headers().contains_key(ALLOW)
+    {
+        set_allow_header(&mut response, allowed);
+    }
+    response
+}
+
+fn set_allow_header(response: &mut Response, allowed: &[Method]) {
+    let allow = allowed
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if let Ok(allow) = allow.parse() {
+        response.headers_mut().insert(ALLOW, allow);
+    }
+}
+
+/// Set of methods route is registered for. Filters can be combined with `|`.
+///
+/// ```
+/// use core::route::{MethodFilter, Router};
+///
+/// fn handler() {}
+///
+/// let app = Router::default().on(MethodFilter::PUT | MethodFilter::PATCH, "/user", handler);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodFilter(u16);
+
+impl MethodFilter {
+    pub const GET: Self = Self(1);
+    pub const POST: Self = Self(1 << 1);
+    pub const PUT: Self = Self(1 << 2);
+    pub const PATCH: Self = Self(1 << 3);
+    pub const DELETE: Self = Self(1 << 4);
+    pub const HEAD: Self = Self(1 << 5);
+    pub const OPTIONS: Self = Self(1 << 6);
+    pub const TRACE: Self = Self(1 << 7);
+
+    /// All of the methods above.
+    pub const ANY: Self = Self((1 << 8) - 1);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns methods included in the filter.
+    pub fn methods(self) -> Vec<Method> {
+        [
+            (Self::GET, Method::GET),
+            (Self::POST, Method::POST),
+            (Self::PUT, Method::PUT),
+            (Self::PATCH, Method::PATCH),
+            (Self::DELETE, Method::DELETE),
+            (Self::HEAD, Method::HEAD),
+            (Self::OPTIONS, Method::OPTIONS),
+            (Self::TRACE, Method::TRACE),
+        ]
+        .into_iter()
+        .filter(|(filter, _)| self.contains(*filter))
+        .map(|(_, method)| method)
+        .collect()
+    }
+}
+
+impl BitOr for MethodFilter {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Display for MethodFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let methods = self.methods();
+        let methods: Vec<_> = methods.iter().map(Method::as_str).collect();
+        f.write_str(&methods.join(" | "))
+    }
+}
+
+/// Generates builder method for every method filter.
+macro_rules! method_routes {
+    ($state:ty; $($name:ident => $filter:ident,)*) => {
+        $(
+            #[doc = concat!("Registers ", stringify!($filter), " route.")]
+            pub fn $name<P, H, Q: 'static>(self, path: P, handler: H) -> Self
+            where
+                P: ToString,
+                H: HandlerTrait<Q, $state>,
+            {
+                self.on(MethodFilter::$filter, path, handler)
+            }
+        )*
+    };
+}
+
 impl<S> Router<S>
 where
     S: Send + Sync + 'static,
@@ -59,42 +397,292 @@ where
     /// let app = Router::with_state(100).get("/", handler);
     /// ```
     pub fn with_state(state: S) -> Self {
+        Self::with_state_cell(StateCell(OnceLock::from(StateRef::Own(Arc::new(state)))))
+    }
+
+    /// Creates Router that takes state from the router it's nested in or merged into,
+    /// see `Router::nest`. Requests to router that never got the state fail with 500.
+    ///
+    /// ```
+    /// use core::route::Router;
+    /// use core::request::State;
+    ///
+    /// fn handler(State(name): State<&'static str>) -> &'static str {
+    ///     name
+    /// }
+    ///
+    /// let api = Router::inherit_state().get("/name", handler);
+    /// let app = Router::with_state("app").nest("/api", api);
+    /// ```
+    pub fn inherit_state() -> Self {
+        Self::with_state_cell(StateCell(OnceLock::new()))
+    }
+
+    fn with_state_cell(state: StateCell<S>) -> Self {
         Self {
             state: Arc::new(state),
             routes: HashMap::new(),
             middlewares: vec![],
+            fallback: None,
+            error_handler: None,
+            body_limit: None,
         }
     }
 
-    fn register_path<P, H, Q: 'static>(mut self, method: Method, path: P, handler: H) -> Self
+    /// Registers handler for every method in the filter. HEAD requests are
+    /// answered by GET routes and OPTIONS ones with `Allow` header, unless
+    /// routes for these methods are registered. Server never sends body
+    /// of a response to HEAD request, whichever handler answered it.
+    pub fn on<P, H, Q: 'static>(mut self, filter: MethodFilter, path: P, handler: H) -> Self
     where
         P: ToString,
         H: HandlerTrait<Q, S>,
     {
-        self.routes.entry(method).or_default().push(
-            Route::new(
-                path.to_string(),
-                BoxCloneService::new(handler.into_service_with_state_arc(self.state.clone())),
-            )
-            .expect("tried to register invalid GET route"),
-        );
+        let route = Route::new(path.to_string(), BoxCloneService::new(self.bind(handler)))
+            .unwrap_or_else(|e| panic!("tried to register invalid {} route: {}", filter, e));
+
+        for method in filter.methods() {
+            self.add_route(method, route.clone());
+        }
         self
     }
 
-    pub fn get<P, H, Q: 'static>(self, path: P, handler: H) -> Self
+    /// Turns handler into service that gets router's state.
+    fn bind<H, Q>(&self, handler: H) -> WithState<H, S, Q>
     where
-        P: ToString,
         H: HandlerTrait<Q, S>,
     {
-        self.register_path(Method::GET, path, handler)
+        WithState {
+            handler,
+            state: self.state.clone(),
+            _marker: PhantomData,
+        }
     }
 
-    pub fn post<P, H, Q: 'static>(self, path: P, handler: H) -> Self
+    /// Adds route to the method's tree, panics if it conflicts with already registered one.
+    fn add_route(&mut self, method: Method, route: Route) {
+        let path = route.metadata.origin().to_string();
+
+        if let Err(e) = self.routes.entry(method).or_default().insert(&path, route) {
+            panic!("{}", e);
+        }
+    }
+
+    method_routes!(S;
+        get => GET,
+        post => POST,
+        put => PUT,
+        patch => PATCH,
+        delete => DELETE,
+        head => HEAD,
+        options => OPTIONS,
+        trace => TRACE,
+        any => ANY,
+    );
+
+    /// Sets handler called when no route matches request, instead of answering
+    /// with plain 404 or 405. Methods registered for request's path can be read
+    /// from `route::AllowedMethods` request extension, 405 responses returned
+    /// by the handler get `Allow` header if they don't set it themselves.
+    ///
+    /// ```
+    /// use core::route::Router;
+    /// use hyper::{Body, Response, StatusCode};
+    ///
+    /// fn not_found() -> Response<Body> {
+    ///     Response::builder()
+    ///         .status(StatusCode::NOT_FOUND)
+    ///         .body(Body::from("nothing here"))
+    ///         .unwrap()
+    /// }
+    ///
+    /// let app = Router::default().fallback(not_found);
+    /// ```
+    pub fn fallback<H, Q: 'static>(mut self, handler: H) -> Self
     where
-        P: ToString,
         H: HandlerTrait<Q, S>,
     {
-        self.register_path(Method::POST, path, handler)
+        self.fallback = Some(Arc::new(BoxCloneService::new(self.bind(handler))));
+        self
+    }
+
+    /// Sets function that renders unhandled errors, i.e. errors returned by handlers
+    /// or middlewares that are not `HttpError` or `Rejection`. By default they are
+    /// logged and answered with 500 and error's message. The function replaces
+    /// both, so it's responsible for logging as well.
+    ///
+    /// ```
+    /// use core::error::HttpError;
+    /// use core::route::Router;
+    ///
+    /// fn handler() -> anyhow::Result<String> {
+    ///     anyhow::bail!("database is down")
+    /// }
+    ///
+    /// let app = Router::default()
+    ///     .get("/", handler)
+    ///     .error_handler(|err: anyhow::Error| {
+    ///         log::error!("request failed: {:?}", err);
+    ///         HttpError::new(hyper::StatusCode::INTERNAL_SERVER_ERROR, "something went wrong")
+    ///     });
+    /// ```
+    pub fn error_handler<F, R>(mut self, f: F) -> Self
+    where
+        F: Fn(anyhow::Error) -> R + Send + Sync + 'static,
+        R: Responder,
+    {
+        self.error_handler = Some(Arc::new(move |err| match f(err).into_response() {
+            Ok(response) => response,
+            Err(err) => {
+                error!("error handler failed: {:?}", err);
+                hyper::Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        }));
+        self
+    }
+
+    /// Registers global middleware, run for every request, also when no route
+    /// matches. The first registered middleware is the outermost one, global
+    /// middlewares wrap middlewares of groups, which wrap route's own ones.
+    ///
+    /// ```
+    /// use core::middleware::LogMiddleware;
+    /// use core::route::Router;
+    ///
+    /// fn handler() {}
+    ///
+    /// let app = Router::default().middleware(LogMiddleware {}).get("/", handler);
+    /// ```
+    pub fn middleware<M>(mut self, m: M) -> Self
+    where
+        M: AroundMiddleware + 'static,
+    {
+        self.middlewares.push(Arc::new(m));
+        self
+    }
+
+    /// Sets maximum size of request's body read by extractors, `body::DEFAULT_BODY_LIMIT`
+    /// by default. Bodies over the limit are rejected with 413. Limit of nested or merged
+    /// router applies to its routes, `body::BodyLimit` middleware overrides it for
+    /// routes of `RouteGroup`. Server doesn't cap bodies on its own, unless
+    /// `Server::max_body_size` is set.
+    ///
+    /// ```
+    /// use core::route::Router;
+    ///
+    /// fn comment(body: String) {}
+    /// fn upload(body: hyper::body::Bytes) {}
+    ///
+    /// let uploads = Router::default()
+    ///     .body_limit(100 * 1024 * 1024)
+    ///     .post("/upload", upload);
+    ///
+    /// let app = Router::default()
+    ///     .body_limit(16 * 1024)
+    ///     .post("/comment", comment)
+    ///     .merge(uploads);
+    /// ```
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = Some(BodyLimit(limit));
+        self
+    }
+
+    /// Adds routes of another router under the prefix, router's route `/`
+    /// becomes the prefix itself. Middlewares of nested router wrap only its
+    /// routes and are run after middlewares of this router. Nested router's
+    /// fallback and error handler are not used.
+    ///
+    /// Nested router keeps its own state. Router created with `Router::inherit_state`
+    /// gets state of this router, it has to be of the same type then.
+    /// Panics when prefix is invalid, when nested router can't get its state
+    /// or when its routes conflict with already registered ones.
+    ///
+    /// ```
+    /// use core::route::Router;
+    /// use core::request::State;
+    ///
+    /// fn user(State(db): State<&'static str>) {}
+    /// fn metrics(State(count): State<u64>) {}
+    ///
+    /// let users = Router::inherit_state().get("/", user).get("/<id>", user);
+    /// let metrics = Router::with_state(0u64).get("/metrics", metrics);
+    ///
+    /// let app = Router::with_state("postgres://")
+    ///     .nest("/users", users)
+    ///     .nest("/internal", metrics);
+    /// ```
+    pub fn nest<P, S2>(self, prefix: P, router: Router<S2>) -> Self
+    where
+        P: ToString,
+        S2: Send + Sync + 'static,
+    {
+        let prefix = prefix.to_string();
+        if !prefix.starts_with('/') || prefix.ends_with('/') {
+            panic!(
+                "invalid prefix {}: it has to start with '/' and can't end with it",
+                prefix
+            );
+        }
+
+        self.add_router(&prefix, router)
+    }
+
+    /// Adds routes of another router, like `Router::nest` without a prefix.
+    /// Other router's fallback is used if this one has none.
+    pub fn merge<S2>(mut self, router: Router<S2>) -> Self
+    where
+        S2: Send + Sync + 'static,
+    {
+        if self.fallback.is_none() {
+            self.fallback = router.fallback.clone();
+        }
+
+        self.add_router("", router)
+    }
+
+    fn add_router<S2>(mut self, prefix: &str, router: Router<S2>) -> Self
+    where
+        S2: Send + Sync + 'static,
+    {
+        if router.state.0.get().is_none() {
+            let parent = (&self.state as &dyn Any).downcast_ref::<Arc<StateCell<S2>>>();
+            match parent {
+                Some(parent) => {
+                    let _ = router.state.0.set(StateRef::Inherited(parent.clone()));
+                }
+                None => panic!(
+                    "router added at {:?} has no state and can't inherit {} one",
+                    prefix,
+                    std::any::type_name::<S>()
+                ),
+            }
+        }
+
+        let mut middlewares = router.middlewares.clone();
+        if let Some(limit) = router.body_limit {
+            middlewares.insert(0, Arc::new(limit));
+        }
+
+        for (method, routes) in &router.routes {
+            for (path, route) in routes.iter() {
+                let path = match path {
+                    "/" if !prefix.is_empty() => prefix.to_string(),
+                    path => format!("{}{}", prefix, path),
+                };
+
+                let mut route = route.clone();
+                route.metadata = RouteMetadata::try_from(path.clone())
+                    .unwrap_or_else(|e| panic!("tried to register invalid route {}: {}", path, e));
+                let own = std::mem::take(&mut route.middlewares);
+                route.middlewares = middlewares.iter().cloned().chain(own).collect();
+
+                self.add_route(method.clone(), route);
+            }
+        }
+        self
     }
 
     /// Takes vector of `route::RouteGroup` and adds them to already registerd routes.
@@ -102,7 +690,7 @@ where
         groups.into_iter().for_each(|rg| {
             for (method, rs) in rg.routes() {
                 for r in rs {
-                    self.routes.entry(method.clone()).or_default().push(r);
+                    self.add_route(method.clone(), r);
                 }
             }
         });
@@ -110,26 +698,71 @@ where
     }
 }
 
-impl<S> Service<Request<Body>> for Router<S> {
-    fn call(&self, req: Request<Body>) -> Response {
-        match self.call(req) {
-            Ok(response) => response,
-            Err(err) => hyper::Response::builder()
-                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
-                .body(hyper::Body::from(err.to_string()))
-                .unwrap(),
+impl<S> Service<Request<Body>> for Router<S>
+where
+    S: Send + Sync,
+{
+    /// Runs global middlewares around routing.
+    fn call(&self, mut req: Request<Body>) -> Response {
+        if let Some(limit) = self.body_limit {
+            req.extensions_mut().insert(limit);
+        }
+
+        let endpoint = |req| -> BoxFuture<Response> {
+            let response = self.call(req).unwrap_or_else(internal_error);
+            Box::pin(std::future::ready(response))
+        };
+
+        let response = Next::new(&self.middlewares, &endpoint).run(req);
+        self.handle_error(response)
+    }
+
+    fn call_async<'a>(&'a self, mut req: Request<Body>) -> BoxFuture<'a, Response> {
+        Box::pin(async move {
+            if let Some(limit) = self.body_limit {
+                req.extensions_mut().insert(limit);
+            }
+
+            let endpoint = |req| -> BoxFuture<Response> {
+                Box::pin(async move {
+                    self.call_routed_async(req)
+                        .await
+                        .unwrap_or_else(internal_error)
+                })
+            };
+
+            let response = Next::new(&self.middlewares, &endpoint).run_async(req).await;
+            self.handle_error(response)
+        })
+    }
+
+    fn is_blocking(&self, req: &Request<Body>) -> bool {
+        Router::is_blocking(self, req)
+    }
+}
+
+impl<S> Router<S> {
+    /// Renders unhandled error of the response with `Router::error_handler`.
+    fn handle_error(&self, mut response: Response) -> Response {
+        if let Some(UnhandledError(err)) = response.extensions_mut().remove::<UnhandledError>() {
+            match &self.error_handler {
+                Some(error_handler) => response = error_handler(err),
+                None => error!("unhandled error: {:?}", err),
+            }
         }
+        response
     }
 }
 
 /// RouteGroup enables grouping endpoints with common prefix path.
+/// Takes handlers like `Router` does, as well as already built services.
 ///
 /// ```
 /// use core::route::RouteGroup;
 /// use core::route::Router;
 /// use crate::core::handler::HandlerTraitWithoutState;
 ///
-/// let v1 = RouteGroup::new("/v1").get("/user", (|| "v1").into_service());
+/// let v1 = RouteGroup::new("/v1").get("/user", || "v1");
 /// let v2 = RouteGroup::new("/v2").get("/user", (|| "v2").into_service());
 ///
 /// Router::default().groups(vec![v1, v2]);
@@ -141,7 +774,7 @@ pub struct RouteGroup {
 
     /// Registered middlewares on specific RouteGroup. These will
     /// be passed to each route.
-    middlewares: Vec<Box<dyn Middleware>>,
+    middlewares: Vec<Arc<dyn AroundMiddleware>>,
 }
 
 impl RouteGroup {
@@ -157,12 +790,14 @@ impl RouteGroup {
     }
 
     /// Injects middlewares for registered routes and returns them.
+    /// Group's middlewares wrap route's own ones.
     pub fn routes(&self) -> HashMap<Method, Vec<Route>> {
         let mut routes = self.routes.clone();
 
         for (_, rs) in routes.iter_mut() {
             for r in rs {
-                r.middlewares = self.middlewares.clone();
+                let own = std::mem::take(&mut r.middlewares);
+                r.middlewares = self.middlewares.iter().cloned().chain(own).collect();
             }
         }
         routes
@@ -172,74 +807,45 @@ impl RouteGroup {
         format!("{}{}", self.prefix, path.to_string())
     }
 
-    /// Registers GET route.
-    pub fn get<P, V>(mut self, path: P, service: V) -> Self
+    /// Registers handler for every method in the filter.
+    pub fn on<P, H, Q: 'static>(mut self, filter: MethodFilter, path: P, handler: H) -> Self
     where
         P: ToString,
-        V: Service<Request<Body>> + Send + Sync + 'static,
+        H: HandlerTrait<Q>,
     {
         let path = self.construct_path(path);
-
-        self.routes.entry(Method::GET).or_default().push(
-            Route::new(path, BoxCloneService::new(service))
-                .expect("tried to register invalid GET route"),
-        );
-        self
-    }
-
-    /// Registers POST route.
-    pub fn post<P, V>(mut self, path: P, service: V) -> Self
-    where
-        P: ToString,
-        V: Service<Request<Body>> + Send + Sync + 'static,
-    {
-        let path = self.construct_path(path);
-
-        self.routes.entry(Method::POST).or_default().push(
-            Route::new(path, BoxCloneService::new(service))
-                .expect("tried to register invalid POST route"),
-        );
-        self
-    }
-
-    /// Registers PUT route.
-    pub fn put<P, V>(mut self, path: P, service: V) -> Self
-    where
-        P: ToString,
-        V: Service<Request<Body>> + Send + Sync + 'static,
-    {
-        let path = self.construct_path(path);
-
-        self.routes.entry(Method::PUT).or_default().push(
-            Route::new(path, BoxCloneService::new(service))
-                .expect("tried to register invalid PUT route"),
-        );
+        let route = Route::new(
+            path,
+            BoxCloneService::new(handler.into_service_with_state(())),
+        )
+        .unwrap_or_else(|e| panic!("tried to register invalid {} route: {}", filter, e));
+
+        for method in filter.methods() {
+            self.routes.entry(method).or_default().push(route.clone());
+        }
         self
     }
 
-    /// Registers DELETE route.
-    pub fn delete<P, V>(mut self, path: P, service: V) -> Self
-    where
-        P: ToString,
-        V: Service<Request<Body>> + Send + Sync + 'static,
-    {
-        let path = self.construct_path(path);
-
-        self.routes.entry(Method::DELETE).or_default().push(
-            Route::new(path, BoxCloneService::new(service))
-                .expect("tried to register invalid DELETE route"),
-        );
-        self
-    }
+    method_routes!(();
+        get => GET,
+        post => POST,
+        put => PUT,
+        patch => PATCH,
+        delete => DELETE,
+        head => HEAD,
+        options => OPTIONS,
+        trace => TRACE,
+        any => ANY,
+    );
 
     /// Registers new middleware.
     /// When calling `RouteProvider::routes` every registered middleware
     /// will be copied into route.
     pub fn middleware<M>(mut self, m: M) -> Self
     where
-        M: Middleware + 'static,
+        M: AroundMiddleware + 'static,
     {
-        self.middlewares.push(Box::new(m));
+        self.middlewares.push(Arc::new(m));
         self
     }
 }
@@ -255,7 +861,7 @@ pub struct Route {
     pub metadata: RouteMetadata,
 
     /// Middlewares for single route.
-    pub middlewares: Vec<Box<dyn Middleware>>,
+    pub middlewares: Vec<Arc<dyn AroundMiddleware>>,
 }
 
 impl Route {
@@ -272,12 +878,13 @@ impl Route {
         })
     }
 
-    pub fn middlewares(mut self, middlewares: Vec<Box<dyn Middleware>>) -> Self {
+    pub fn middlewares(mut self, middlewares: Vec<Arc<dyn AroundMiddleware>>) -> Self {
         self.middlewares = middlewares;
         self
     }
 
-    /// Indicates if request's path match with router's path.
+    /// Indicates if request's path match with router's path. Understands only
+    /// literal and `<param>` segments, `Router` matches with `tree::Tree`.
     ///
     /// '/test/john/doe'  & '/test/<name>/<surn>' => true,
     /// '/test/test/      & '/test/test'          => true,
@@ -306,17 +913,25 @@ impl Route {
         true
     }
 
-    pub fn fire(&self, mut request: Request<Body>) -> anyhow::Result<Response> {
-        for m in &self.middlewares {
-            m.on_request(&mut request)?;
-        }
+    /// Calls route's service wrapped with route's middlewares.
+    pub fn fire(&self, request: Request<Body>) -> anyhow::Result<Response> {
+        let endpoint = |req| self.service.0.call_async(req);
+
+        Ok(Next::new(&self.middlewares, &endpoint).run(request))
+    }
 
-        let mut response = self.service.0.call(request);
+    /// Asynchronous version of `fire`, route has to be non-blocking.
+    pub async fn fire_async(&self, request: Request<Body>) -> anyhow::Result<Response> {
+        let endpoint = |req| self.service.0.call_async(req);
 
-        for m in &self.middlewares {
-            m.on_response(&mut response)?;
-        }
-        Ok(response)
+        Ok(Next::new(&self.middlewares, &endpoint)
+            .run_async(request)
+            .await)
+    }
+
+    /// Tells if route's middlewares or service can block the thread.
+    pub fn is_blocking(&self, request: &Request<Body>) -> bool {
+        self.middlewares.iter().any(|m| m.is_blocking()) || self.service.0.is_blocking(request)
     }
 }
 
@@ -325,10 +940,22 @@ pub struct RouteMetadata {
     /// Original, registered path.
     origin: String,
 
-    /// Holds params' segments index counted as place after '/' character.
+    /// Names of params in order of appearance.
     ///
-    /// `/test/<param1>/<param2>` - { 0: 1, 1: 2 }.
-    pub param_segments: HashMap<usize, usize>,
+    /// `/test/<param1>/<param2>` - ["param1", "param2"].
+    params: Vec<String>,
+}
+
+impl RouteMetadata {
+    /// Returns path route was registered with.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns names of route's params.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
 }
 
 impl TryFrom<String> for RouteMetadata {
@@ -336,45 +963,20 @@ impl TryFrom<String> for RouteMetadata {
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
         Ok(Self {
-            origin: value.clone(),
-            param_segments: parse_param_segments(value)?,
+            params: parse_param_names(&value)?,
+            origin: value,
         })
     }
 }
 
-fn parse_param_segments(value: String) -> anyhow::Result<HashMap<usize, usize>> {
-    let mut param_segments: HashMap<usize, usize> = HashMap::new();
-    let mut segment = String::new();
-    let mut beginning_found = false;
-    let mut slash_counter = 0;
-    let mut found = 0;
-
-    for c in value.chars() {
-        match c {
-            '/' => slash_counter += 1,
-            '<' => {
-                beginning_found = true;
-                continue;
-            }
-            '>' => {
-                beginning_found = false;
-                // slash_counter - 1 because we don't want to consider starting '/'
-                param_segments.insert(found, slash_counter - 1);
-                found += 1;
-                segment.clear();
-                continue;
-            }
-            _ => {
-                if beginning_found {
-                    segment.push(c)
-                }
-            }
-        }
-    }
-
-    if beginning_found {
-        bail!("Invalid url - param segment not closed")
-    }
+fn parse_param_names(value: &str) -> anyhow::Result<Vec<String>> {
+    let names = parse_path(value)?
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Param { name, .. } | Segment::CatchAll(name) => Some(name.to_string()),
+            Segment::Static(_) => None,
+        })
+        .collect();
 
-    Ok(param_segments)
+    Ok(names)
 }
@@ -1,23 +1,120 @@
+#[cfg(feature = "tokio")]
+use crate::response::write_response_async;
 use crate::{
     handler::Service,
-    response::{response_to_bytes, Response},
+    parser::{BodyDecoder, ParseError, ParseLimits, RequestParser},
+    pool::WorkerPool,
+    response::{is_close_delimited, write_response, RequestHead, Response},
+    shutdown::ShutdownHandle,
+    tls::TlsConfig,
 };
-use anyhow::Ok;
-use hyper::{Body, Request};
-use log::error;
+use anyhow::bail;
+use bytes::{Bytes, BytesMut};
+use futures_util::task::AtomicWaker;
+use hyper::{
+    body::Sender,
+    header::{HeaderValue, CONNECTION},
+    http::request::Parts,
+    Body, HeaderMap, Request, StatusCode, Version,
+};
+use log::{debug, error, warn};
 use std::{
+    collections::HashMap,
     io::{Read, Write},
-    net::{TcpListener, TcpStream},
-    sync::Arc,
-    thread,
+    net::{Shutdown, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    time::Duration,
 };
 
-#[derive(Default)]
+/// Default time an idle keep-alive connection waits for the next request.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default limit of requests served over a single connection.
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
+/// Default number of worker threads serving connections.
+const DEFAULT_WORKERS: usize = 32;
+
+/// Default number of accepted connections waiting for a free worker.
+const DEFAULT_QUEUE_DEPTH: usize = 128;
+
+/// How long server tries to send 503 response to a connection that can't be served.
+const REJECT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long server waits before accepting again after running out of resources,
+/// e.g. file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Default time in-flight requests have to finish after shutdown was requested.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How much of request's body left unread by handler is read and discarded,
+/// so the connection can be kept open. Connection with more of it gets closed.
+const MAX_DRAINED_BODY_SIZE: usize = 64 * 1024;
+
 pub struct Server<V> {
     host: String,
     port: u32,
 
     service: Option<V>,
+
+    /// How long an idle persistent connection is kept open waiting for
+    /// the next request. `None` means connection waits forever.
+    keep_alive_timeout: Option<Duration>,
+
+    /// How many requests can be served over one connection before
+    /// it gets closed by the server. `None` means no limit.
+    max_requests_per_connection: Option<usize>,
+
+    /// Limits applied while parsing incoming requests.
+    limits: ParseLimits,
+
+    /// Number of worker threads that serve connections.
+    workers: usize,
+
+    /// Number of accepted connections that can wait for a free worker.
+    /// Connections over that limit get 503 response right away.
+    queue_depth: usize,
+
+    /// Prefix of worker threads' names.
+    thread_name: String,
+
+    /// Stops the server when triggered.
+    shutdown: ShutdownHandle,
+
+    /// How long in-flight requests can take after shutdown was requested.
+    shutdown_timeout: Duration,
+
+    /// Connections served at the moment, closed from outside during shutdown.
+    connections: ConnectionRegistry,
+
+    /// Connections are served over TLS when set.
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl<V> Default for Server<V> {
+    fn default() -> Self {
+        Self {
+            host: String::default(),
+            port: 0,
+            service: None,
+            keep_alive_timeout: Some(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            max_requests_per_connection: Some(DEFAULT_MAX_REQUESTS_PER_CONNECTION),
+            limits: ParseLimits::default(),
+            workers: DEFAULT_WORKERS,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            thread_name: "rhttp-worker".into(),
+            shutdown: ShutdownHandle::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            connections: ConnectionRegistry::default(),
+            tls: None,
+        }
+    }
 }
 
 impl<V> Server<V>
@@ -28,7 +125,7 @@ where
         Self {
             host: host.into(),
             port,
-            service: None,
+            ..Default::default()
         }
     }
 
@@ -37,34 +134,281 @@ where
         self
     }
 
-    /// Starts server,
+    /// Sets how long an idle keep-alive connection waits for the next request.
+    /// Passing `None` disables the timeout.
+    pub fn keep_alive_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Sets how many requests can be served over a single connection.
+    /// Passing `None` removes the limit.
+    pub fn max_requests_per_connection(mut self, max: Option<usize>) -> Self {
+        self.max_requests_per_connection = max;
+        self
+    }
+
+    /// Sets maximum size of request line and headers together.
+    /// Bigger requests are rejected with 431 status code.
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.limits.max_header_size = size;
+        self
+    }
+
+    /// Sets maximum number of request's header fields.
+    /// Requests with more headers are rejected with 431 status code.
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.limits.max_headers = count;
+        self
+    }
+
+    /// Sets maximum size of request's body for all routes, whatever their
+    /// `Router::body_limit` is. Bigger requests are rejected with 413 status code
+    /// and their connection is closed. There is no such limit by default, bodies
+    /// are streamed to handlers and extractors apply route's limit.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.limits.max_body_size = Some(size);
+        self
+    }
+
+    /// Sets number of worker threads serving connections.
+    /// Note that each open connection occupies its worker until it's closed.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how many accepted connections can wait for a free worker.
+    /// When queue is full, new connections get 503 response and are closed.
+    /// TLS connections are closed without the response, sending it would take
+    /// a handshake, which is too expensive for a server that's already overloaded.
+    pub fn queue_depth(mut self, depth: usize) -> Self {
+        self.queue_depth = depth;
+        self
+    }
+
+    /// Sets prefix of worker threads' names, each worker gets its index appended.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = name.into();
+        self
+    }
+
+    /// Sets how long in-flight requests can take after shutdown was requested.
+    /// Connections that are still open after that time are closed.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Returns handle that stops the server, see `core::shutdown::ShutdownHandle`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Serves HTTPS with certificate chain and private key loaded from PEM files.
+    /// See `core::tls::TlsConfig` for SNI and client certificates. Connections over
+    /// `Server::queue_depth` are closed without 503 response.
+    pub fn with_tls(
+        self,
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        self.with_tls_config(TlsConfig::from_pem_files(cert_chain, private_key)?)
+    }
+
+    /// Serves HTTPS configured with `config`.
+    pub fn with_tls_config(mut self, config: TlsConfig) -> anyhow::Result<Self> {
+        self.tls = Some(config.into_server_config()?);
+        Ok(self)
+    }
+
+    /// Starts server, returns after shutdown was requested and in-flight requests finished.
     pub fn run(self) -> anyhow::Result<()> {
         let listener = TcpListener::bind(format!("{}:{}", self.host, self.port))?;
 
+        self.serve(listener)
+    }
+
+    /// Runs server on already bound listener, e.g. one bound to port 0.
+    /// Accepts connections from the listener and passes them to the worker pool.
+    pub fn serve(self, listener: TcpListener) -> anyhow::Result<()> {
+        self.shutdown.set_local_addr(listener.local_addr()?);
         let server = Arc::new(self);
 
-        for stream in listener.incoming() {
-            let stream = stream?;
-            let s = server.clone();
-            thread::spawn(move || {
+        let s = server.clone();
+        let pool = WorkerPool::new(
+            server.workers,
+            server.queue_depth,
+            &server.thread_name,
+            move |stream: TcpStream| {
                 if let Err(e) = s.handle(stream) {
                     error!("got error during handling connection: {}", e);
                 }
-            });
+            },
+        )?;
+
+        while !server.shutdown.is_shutdown() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    std::thread::sleep(accept_backoff(e)?);
+                    continue;
+                }
+            };
+            if server.shutdown.is_shutdown() {
+                break;
+            }
+
+            if let Err(stream) = pool.try_dispatch(stream) {
+                warn!("all workers are busy, rejecting connection");
+                server.reject(stream);
+            }
+        }
+        drop(listener);
+
+        // Idle connections wait for the next request, closing their read side ends
+        // them right away. Busy ones finish current request and close after it.
+        server.connections.shutdown(Shutdown::Read);
+        if !pool.join_timeout(server.shutdown_timeout) {
+            warn!("shutdown timeout passed, closing remaining connections");
+            server.connections.shutdown(Shutdown::Both);
         }
         Ok(())
     }
 
-    /// Calls route's handler and pass response to function that writes to opened stream.
-    fn handle(&self, mut stream: TcpStream) -> anyhow::Result<()> {
-        let response = self.fire::<TcpStream>(parse_request_from_tcp(&mut stream)?)?;
+    /// Prepares TcpStream and serves all requests that come through it.
+    fn handle(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let _registered = self.connections.register(&stream)?;
+        if self.shutdown.is_shutdown() {
+            self.reject(stream);
+            return Ok(());
+        }
+
+        stream.set_read_timeout(self.keep_alive_timeout)?;
+        match &self.tls {
+            Some(config) => {
+                // Handshake happens while the first request is read.
+                let connection = rustls::ServerConnection::new(config.clone())?;
+                let mut stream = rustls::StreamOwned::new(connection, stream);
+                self.serve_connection(&mut stream)?;
+
+                stream.conn.send_close_notify();
+                stream.flush()?;
+                Ok(())
+            }
+            None => self.serve_connection(stream),
+        }
+    }
+
+    /// Closes connection that can't be served. Plain connections get 503 response,
+    /// TLS ones are just closed since that would need a handshake first.
+    fn reject(&self, stream: TcpStream) {
+        if self.tls.is_some() {
+            return;
+        }
+
+        if let Err(e) = reject_connection(stream) {
+            debug!("could not reject connection: {}", e);
+        }
+    }
+
+    /// Reads requests one after another from the connection, calls route's handler
+    /// and writes responses back in the same order. Connection is kept open
+    /// as long as both client and server agree on that.
+    ///
+    /// Body that is not buffered yet is read by another thread while handler runs,
+    /// so handler gets it piece by piece as it arrives.
+    fn serve_connection<T>(&self, stream: T) -> anyhow::Result<()>
+    where
+        T: Read + Write + Send,
+    {
+        let mut connection = Connection::new(stream, self.limits);
+        let mut served = 0;
 
-        let response_bytes: Vec<u8> = response_to_bytes(response)?;
-        stream.write_all(&response_bytes)?;
+        loop {
+            let (parts, mut decoder) = match connection.read_head() {
+                Ok(Some(head)) => head,
+                Ok(None) => break,
+                Err(e) => match e.downcast::<ParseError>() {
+                    Ok(err) => {
+                        debug!("rejecting invalid request: {}", err);
+                        let response = parse_error_response(&err);
+                        connection.write_response(response, &RequestHead::default())?;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            served += 1;
+
+            let (request, sender) = request_with_body(parts, &mut decoder, &mut connection.buffer);
+            let head = RequestHead::of(&request);
+            let (response, keep_alive) = match sender {
+                Some(sender) => {
+                    let (sink, returned) = BodySink::new(sender);
+                    let (dispatched, fed) = std::thread::scope(|scope| {
+                        let feeder = scope.spawn(|| connection.feed_body(decoder, sink));
+                        let dispatched = self.dispatch(request, &head, served);
+                        returned.set();
+                        let fed = feeder
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                        (dispatched, fed)
+                    });
+                    after_body(dispatched?, fed, &head)?
+                }
+                None => self.dispatch(request, &head, served)?,
+            };
+            connection.write_response(response, &head)?;
+
+            if !keep_alive {
+                break;
+            }
+        }
 
         Ok(())
     }
 
+    /// Calls service with request that came as `served`-th over its connection.
+    /// Returns response together with information if connection stays open after it.
+    fn dispatch(
+        &self,
+        request: Request<Body>,
+        head: &RequestHead,
+        served: usize,
+    ) -> anyhow::Result<(Response, bool)> {
+        let keep_alive = self.keeps_alive(&request, served);
+        let response = self.fire::<std::io::Sink>(request)?;
+
+        Ok(self.finish_response(response, head, keep_alive))
+    }
+
+    /// Tells if connection can stay open after the request, as far as request is concerned.
+    fn keeps_alive(&self, request: &Request<Body>, served: usize) -> bool {
+        wants_keep_alive(request)
+            && self
+                .max_requests_per_connection
+                .is_none_or(|max| served < max)
+    }
+
+    /// Sets `Connection` header of the response, returns it together with
+    /// information if connection stays open after it.
+    fn finish_response(
+        &self,
+        mut response: Response,
+        head: &RequestHead,
+        keep_alive: bool,
+    ) -> (Response, bool) {
+        // Server that is shutting down closes connection after in-flight request.
+        let keep_alive = keep_alive
+            && wants_keep_alive(&response)
+            && !is_close_delimited(&response, head)
+            && !self.shutdown.is_shutdown();
+
+        set_connection_header(&mut response, head.version, keep_alive);
+        (response, keep_alive)
+    }
+
     /// Method that runs whole server's logic. Takes Write trait
     /// implementation in order to mock it during testing.
     pub fn fire<W>(&self, request: Request<Body>) -> anyhow::Result<Response>
@@ -75,56 +419,1296 @@ where
     }
 }
 
+#[cfg(feature = "tokio")]
+impl<V> Server<V>
+where
+    V: Service<Request<Body>> + Send + Sync + 'static,
+{
+    /// Starts server on tokio runtime. Connections are accepted, read and written
+    /// asynchronously, so idle keep-alive connections don't occupy any thread.
+    ///
+    /// Requests to `async fn` handlers are run on the runtime, unless a blocking
+    /// middleware (see `AroundMiddleware::is_blocking`) wraps the handler.
+    /// Synchronous handlers and blocking middlewares run on tokio's blocking pool.
+    pub async fn run_async(self) -> anyhow::Result<()> {
+        let listener =
+            tokio::net::TcpListener::bind(format!("{}:{}", self.host, self.port)).await?;
+
+        self.serve_async(listener).await
+    }
+
+    /// Runs async server on already bound listener.
+    pub async fn serve_async(self, listener: tokio::net::TcpListener) -> anyhow::Result<()> {
+        let tls = self.tls.clone().map(tokio_rustls::TlsAcceptor::from);
+        let server = Arc::new(self);
+        let mut connections = tokio::task::JoinSet::new();
+
+        loop {
+            let (stream, _) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tokio::time::sleep(accept_backoff(e)?).await;
+                        continue;
+                    }
+                },
+                // Reaps finished connections, so the set doesn't grow.
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                _ = server.shutdown.wait() => break,
+            };
+
+            let s = server.clone();
+            let tls = tls.clone();
+            connections.spawn(async move {
+                let served = match tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok(stream) => s.serve_connection_async(stream).await,
+                        Err(e) => Err(e.into()),
+                    },
+                    None => s.serve_connection_async(stream).await,
+                };
+                if let Err(e) = served {
+                    error!("got error during handling connection: {}", e);
+                }
+            });
+        }
+        drop(listener);
+
+        let finished = async { while connections.join_next().await.is_some() {} };
+        if tokio::time::timeout(server.shutdown_timeout, finished)
+            .await
+            .is_err()
+        {
+            warn!("shutdown timeout passed, closing remaining connections");
+            connections.shutdown().await;
+        }
+        Ok(())
+    }
+
+    /// Asynchronous version of `serve_connection`.
+    async fn serve_connection_async<T>(self: Arc<Self>, mut stream: T) -> anyhow::Result<()>
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let mut buffer = BytesMut::with_capacity(MESSAGE_SIZE);
+        let mut parser = RequestParser::new(self.limits);
+        let mut served = 0;
+
+        loop {
+            let (parts, mut decoder) = match self
+                .read_head_async(&mut stream, &mut buffer, &mut parser)
+                .await
+            {
+                Ok(Some(head)) => head,
+                Ok(None) => break,
+                Err(e) => match e.downcast::<ParseError>() {
+                    Ok(err) => {
+                        debug!("rejecting invalid request: {}", err);
+                        let response = parse_error_response(&err);
+                        write_response_async(response, &RequestHead::default(), &mut stream)
+                            .await?;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            served += 1;
+
+            let (request, sender) = request_with_body(parts, &mut decoder, &mut buffer);
+            let head = RequestHead::of(&request);
+            let dispatched = self.clone().dispatch_async(request, head.clone(), served);
+            let (response, keep_alive) = match sender {
+                Some(sender) => {
+                    let (sink, returned) = BodySink::new(sender);
+                    let dispatched = async {
+                        let dispatched = dispatched.await;
+                        returned.set();
+                        dispatched
+                    };
+                    let fed = self.feed_body_async(&mut stream, &mut buffer, decoder, sink);
+                    let (dispatched, fed) = tokio::join!(dispatched, fed);
+                    after_body(dispatched?, fed, &head)?
+                }
+                None => dispatched.await?,
+            };
+            write_response_async(response, &head, &mut stream).await?;
+
+            if !keep_alive {
+                break;
+            }
+        }
+
+        // Sends TLS close_notify, plain connections get their write side closed.
+        // Client might be gone already, so the result doesn't matter.
+        let _ = tokio::io::AsyncWriteExt::shutdown(&mut stream).await;
+        Ok(())
+    }
+
+    /// Awaits service on the runtime, blocking services are called on tokio's blocking pool.
+    async fn dispatch_async(
+        self: Arc<Self>,
+        request: Request<Body>,
+        head: RequestHead,
+        served: usize,
+    ) -> anyhow::Result<(Response, bool)> {
+        let service = self.service.as_ref().unwrap();
+        if service.is_blocking(&request) {
+            return tokio::task::spawn_blocking(move || self.dispatch(request, &head, served))
+                .await?;
+        }
+
+        let keep_alive = self.keeps_alive(&request, served);
+        let response = service.call_async(request).await;
+
+        Ok(self.finish_response(response, &head, keep_alive))
+    }
+
+    /// Asynchronous version of `Connection::read_head`.
+    async fn read_head_async<T>(
+        &self,
+        stream: &mut T,
+        buffer: &mut BytesMut,
+        parser: &mut RequestParser,
+    ) -> anyhow::Result<Option<(Parts, BodyDecoder)>>
+    where
+        T: tokio::io::AsyncRead + Unpin,
+    {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            if let Some(head) = parser.parse(buffer)? {
+                return Ok(Some(head));
+            }
+
+            let waiting = buffer.is_empty() && parser.is_idle();
+
+            // `None` means that read timed out.
+            let read = async {
+                match self.keep_alive_timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, stream.read_buf(buffer))
+                        .await
+                        .ok(),
+                    None => Some(stream.read_buf(buffer).await),
+                }
+            };
+
+            // Waiting for the next request is interrupted by shutdown.
+            let read = if waiting {
+                tokio::select! {
+                    read = read => read,
+                    _ = self.shutdown.wait() => return Ok(None),
+                }
+            } else {
+                read.await
+            };
+
+            let bytes_read = match read {
+                Some(result) => result?,
+                None if waiting => return Ok(None),
+                None => return Err(ParseError::timeout().into()),
+            };
+
+            match bytes_read {
+                0 if waiting => return Ok(None),
+                0 => bail!("connection closed in the middle of a request"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Asynchronous version of `Connection::feed_body`.
+    async fn feed_body_async<T>(
+        &self,
+        stream: &mut T,
+        buffer: &mut BytesMut,
+        mut decoder: BodyDecoder,
+        mut sink: BodySink,
+    ) -> anyhow::Result<bool>
+    where
+        T: tokio::io::AsyncRead + Unpin,
+    {
+        loop {
+            match self
+                .read_body_chunk_async(stream, buffer, &mut decoder)
+                .await
+            {
+                Ok(Some(chunk)) => {
+                    if !sink.send(chunk).await {
+                        return Ok(false);
+                    }
+                }
+                Ok(None) => return Ok(true),
+                Err(e) => {
+                    sink.abort();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Asynchronous version of `Connection::read_body_chunk`.
+    async fn read_body_chunk_async<T>(
+        &self,
+        stream: &mut T,
+        buffer: &mut BytesMut,
+        decoder: &mut BodyDecoder,
+    ) -> anyhow::Result<Option<Bytes>>
+    where
+        T: tokio::io::AsyncRead + Unpin,
+    {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            if let Some(chunk) = decoder.decode(buffer)? {
+                return Ok(Some(chunk));
+            }
+            if decoder.is_finished() {
+                return Ok(None);
+            }
+
+            let bytes_read = match self.keep_alive_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, stream.read_buf(buffer))
+                    .await
+                    .map_err(|_| ParseError::timeout())??,
+                None => stream.read_buf(buffer).await?,
+            };
+            if bytes_read == 0 {
+                bail!("connection closed in the middle of a request");
+            }
+        }
+    }
+}
+
+/// Builds request with the body that follows its head. Body that is already
+/// buffered is passed as it is, otherwise request gets the receiving end
+/// of a channel and returned sender has to be fed with the body.
+fn request_with_body(
+    parts: Parts,
+    decoder: &mut BodyDecoder,
+    buffer: &mut BytesMut,
+) -> (Request<Body>, Option<Sender>) {
+    match decoder.take_buffered(buffer) {
+        Some(body) => (Request::from_parts(parts, body.into()), None),
+        None => {
+            let (sender, body) = Body::channel();
+            (Request::from_parts(parts, body), Some(sender))
+        }
+    }
+}
+
+/// Settles response to the request which body was fed to the handler while it ran.
+/// Connection that has unread body left is closed after the response, invalid
+/// body is answered like any other malformed request.
+fn after_body(
+    dispatched: (Response, bool),
+    fed: anyhow::Result<bool>,
+    head: &RequestHead,
+) -> anyhow::Result<(Response, bool)> {
+    let (mut response, keep_alive) = dispatched;
+    match fed {
+        Ok(true) => Ok((response, keep_alive)),
+        Ok(false) => {
+            set_connection_header(&mut response, head.version, false);
+            Ok((response, false))
+        }
+        Err(e) => match e.downcast::<ParseError>() {
+            Ok(err) => {
+                debug!("rejecting invalid request body: {}", err);
+                Ok((parse_error_response(&err), false))
+            }
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// Set once handler returned, request's body is not passed to it after that.
+#[derive(Default)]
+struct HandlerReturned {
+    returned: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl HandlerReturned {
+    fn set(&self) {
+        self.returned.store(true, Ordering::SeqCst);
+        self.waker.wake();
+    }
+}
+
+/// Request's body on its way from the connection to the handler. Handler gets
+/// the body until it drops it or returns, rest of the body is discarded then.
+/// Body kept after that, e.g. in response, fails instead of ending early.
+struct BodySink {
+    sender: Option<Sender>,
+    returned: Arc<HandlerReturned>,
+
+    /// Size of body discarded so far.
+    drained: usize,
+}
+
+impl BodySink {
+    /// Returns sink together with flag to set once handler returned.
+    fn new(sender: Sender) -> (Self, Arc<HandlerReturned>) {
+        let returned = Arc::new(HandlerReturned::default());
+        let sink = Self {
+            sender: Some(sender),
+            returned: returned.clone(),
+            drained: 0,
+        };
+        (sink, returned)
+    }
+
+    /// Passes chunk to the handler, waiting until it takes the previous one.
+    /// Returns false once too much of the body was discarded.
+    async fn send(&mut self, mut chunk: Bytes) -> bool {
+        if let Some(sender) = &mut self.sender {
+            let returned = &self.returned;
+            let ready = std::future::poll_fn(|cx| {
+                returned.waker.register(cx.waker());
+                if returned.returned.load(Ordering::SeqCst) {
+                    return Poll::Ready(false);
+                }
+                sender.poll_ready(cx).map(|ready| ready.is_ok())
+            })
+            .await;
+
+            if ready {
+                match sender.try_send_data(chunk) {
+                    Ok(()) => return true,
+                    Err(rejected) => chunk = rejected,
+                }
+            }
+            self.abort_sender();
+        }
+
+        self.drained += chunk.len();
+        self.drained <= MAX_DRAINED_BODY_SIZE
+    }
+
+    /// Makes handler's body fail, e.g. when connection broke before its end.
+    fn abort(mut self) {
+        self.abort_sender();
+    }
+
+    fn abort_sender(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.abort();
+        }
+    }
+}
+
+/// Indicates if message allows connection to stay open after it.
+/// HTTP/1.1 connections are persistent unless `Connection: close` is sent,
+/// HTTP/1.0 connections are closed unless `Connection: keep-alive` is sent.
+fn wants_keep_alive<T>(message: &T) -> bool
+where
+    T: HttpMessage,
+{
+    let connection = message
+        .headers()
+        .get(CONNECTION)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase());
+
+    match connection.as_deref() {
+        Some(v) if v.split(',').any(|t| t.trim() == "close") => false,
+        Some(v) if v.split(',').any(|t| t.trim() == "keep-alive") => true,
+        _ => message.version() >= Version::HTTP_11,
+    }
+}
+
+/// Informs client whether connection stays open after the response.
+fn set_connection_header(response: &mut Response, version: Version, keep_alive: bool) {
+    if !keep_alive {
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+    } else if version < Version::HTTP_11 {
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+    }
+}
+
+/// Common view over request and response used for connection management.
+trait HttpMessage {
+    fn headers(&self) -> &HeaderMap;
+    fn version(&self) -> Version;
+}
+
+impl<B> HttpMessage for hyper::Request<B> {
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+
+    fn version(&self) -> Version {
+        self.version()
+    }
+}
+
+impl<B> HttpMessage for hyper::Response<B> {
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+
+    fn version(&self) -> Version {
+        self.version()
+    }
+}
+
 const MESSAGE_SIZE: usize = 1024;
 
-/// Takes TcpStream, reads whole content and parses it to a http request.
-fn parse_request_from_tcp(stream: &mut TcpStream) -> anyhow::Result<Request<Body>> {
-    // Store all the bytes for our received String
-    let mut received: Vec<u8> = vec![];
+/// Buffered client connection. Bytes read past the end of one request are kept
+/// for the next one, that's how pipelined requests are served in order.
+struct Connection<T> {
+    stream: T,
+    buffer: BytesMut,
+    parser: RequestParser,
+}
+
+impl<T> Connection<T>
+where
+    T: Read + Write,
+{
+    fn new(stream: T, limits: ParseLimits) -> Self {
+        Self {
+            stream,
+            buffer: BytesMut::with_capacity(MESSAGE_SIZE),
+            parser: RequestParser::new(limits),
+        }
+    }
+
+    /// Reads head of the next request from the connection. Returns `None` if client
+    /// closed the connection or idle timeout passed while waiting for a new request.
+    /// Malformed requests are reported with `ParseError`.
+    fn read_head(&mut self) -> anyhow::Result<Option<(Parts, BodyDecoder)>> {
+        loop {
+            if let Some(head) = self.parser.parse(&mut self.buffer)? {
+                return Ok(Some(head));
+            }
+
+            let waiting = self.buffer.is_empty() && self.parser.is_idle();
+            match self.fill_buffer() {
+                Ok(0) if waiting => return Ok(None),
+                Ok(0) => bail!("connection closed in the middle of a request"),
+                Ok(_) => {}
+                Err(e) if waiting && is_timeout(&e) => return Ok(None),
+                Err(e) if is_timeout(&e) => return Err(ParseError::timeout().into()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Reads next piece of request's body, `None` once whole body was read.
+    fn read_body_chunk(&mut self, decoder: &mut BodyDecoder) -> anyhow::Result<Option<Bytes>> {
+        loop {
+            if let Some(chunk) = decoder.decode(&mut self.buffer)? {
+                return Ok(Some(chunk));
+            }
+            if decoder.is_finished() {
+                return Ok(None);
+            }
+
+            match self.fill_buffer() {
+                Ok(0) => bail!("connection closed in the middle of a request"),
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => return Err(ParseError::timeout().into()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Passes request's body to the handler as it arrives. Returns false when
+    /// connection can't be reused, since the body was not read to its end.
+    fn feed_body(&mut self, mut decoder: BodyDecoder, mut sink: BodySink) -> anyhow::Result<bool> {
+        loop {
+            match self.read_body_chunk(&mut decoder) {
+                Ok(Some(chunk)) => {
+                    if !futures_executor::block_on(sink.send(chunk)) {
+                        return Ok(false);
+                    }
+                }
+                Ok(None) => return Ok(true),
+                Err(e) => {
+                    sink.abort();
+                    return Err(e);
+                }
+            }
+        }
+    }
 
-    // Array with a fixed size
-    let mut rx_bytes = [0u8; MESSAGE_SIZE];
-    loop {
-        // Read from the current data in the TcpStream
-        let bytes_read = stream.read(&mut rx_bytes)?;
+    fn fill_buffer(&mut self) -> std::io::Result<usize> {
+        let mut rx_bytes = [0u8; MESSAGE_SIZE];
+        let bytes_read = self.stream.read(&mut rx_bytes)?;
+        self.buffer.extend_from_slice(&rx_bytes[..bytes_read]);
+        Ok(bytes_read)
+    }
+
+    fn write_response(&mut self, response: Response, head: &RequestHead) -> anyhow::Result<()> {
+        write_response(response, head, &mut self.stream)
+    }
+}
+
+/// Keeps clones of connections served by the synchronous server, so they
+/// can be closed from outside of their workers during shutdown.
+#[derive(Default)]
+struct ConnectionRegistry {
+    next_id: AtomicUsize,
+    streams: Mutex<HashMap<usize, TcpStream>>,
+}
 
-        // However many bytes we read, extend the `received` string bytes
-        received.extend_from_slice(&rx_bytes[..bytes_read]);
+impl ConnectionRegistry {
+    /// Registers connection until returned guard is dropped.
+    fn register(&self, stream: &TcpStream) -> std::io::Result<Registered<'_>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.streams.lock().unwrap().insert(id, stream.try_clone()?);
 
-        // If we didn't fill the array
-        // stop reading because there's no more data (we hope!)
-        if bytes_read < MESSAGE_SIZE {
-            break;
+        Ok(Registered { registry: self, id })
+    }
+
+    fn shutdown(&self, how: Shutdown) {
+        for stream in self.streams.lock().unwrap().values() {
+            let _ = stream.shutdown(how);
         }
     }
-    let mut headers = [httparse::EMPTY_HEADER; 64];
-    let mut req = httparse::Request::new(&mut headers);
+}
+
+/// Removes connection from the registry when dropped.
+struct Registered<'a> {
+    registry: &'a ConnectionRegistry,
+    id: usize,
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        self.registry.streams.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Answers connection that can't be served with 503 response without reading its request.
+fn reject_connection(mut stream: TcpStream) -> anyhow::Result<()> {
+    stream.set_write_timeout(Some(REJECT_WRITE_TIMEOUT))?;
 
-    let b_inx = req.parse(&received).unwrap().unwrap();
+    let mut response = hyper::Response::new(Body::from("server is overloaded"));
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    response
+        .headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("close"));
 
-    httparse_req_to_hyper_request(req, received[b_inx..].to_vec())
+    write_response(response, &RequestHead::default(), &mut stream)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    Ok(())
 }
 
-fn httparse_req_to_hyper_request(
-    req: httparse::Request,
-    body: Vec<u8>,
-) -> anyhow::Result<hyper::Request<Body>> {
-    let mut builder = hyper::Request::builder()
-        .method(req.method.unwrap())
-        .uri(req.path.unwrap());
+/// Decides how long to wait before accepting again after `accept` failed. Errors
+/// of the connection being accepted are skipped, others (e.g. running out of file
+/// descriptors under load) are waited out. Returns the error if listener is unusable.
+fn accept_backoff(e: std::io::Error) -> std::io::Result<Duration> {
+    use std::io::ErrorKind;
 
-    for header in req.headers {
-        builder = builder.header(header.name, header.value);
+    match e.kind() {
+        ErrorKind::InvalidInput | ErrorKind::Unsupported => Err(e),
+        ErrorKind::ConnectionAborted
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::Interrupted
+        | ErrorKind::WouldBlock => {
+            debug!("could not accept connection: {}", e);
+            Ok(Duration::ZERO)
+        }
+        _ => {
+            error!("could not accept connection: {}", e);
+            Ok(ACCEPT_BACKOFF)
+        }
     }
+}
 
-    Ok(builder.body(Body::from(body))?)
+/// Builds response sent to the client whose request could not be parsed.
+fn parse_error_response(err: &ParseError) -> Response {
+    let mut response = hyper::Response::new(Body::from(err.message.clone()));
+    *response.status_mut() = err.status;
+    response
+        .headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("close"));
+    response
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
 }
 
 #[cfg(test)]
 mod tests {
+    use super::Server;
+    use crate::body::BodyStream;
     use crate::handler::BoxCloneService;
     use crate::handler::HandlerTrait;
-    use crate::route::Route;
+    use crate::request::Rejection;
+    use crate::route::{Route, Router};
+    use std::{
+        io::{Cursor, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, Mutex},
+    };
+
+    /// In-memory connection, reads prepared input and collects everything written.
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: &str) -> Self {
+            Self {
+                input: Cursor::new(input.as_bytes().to_vec()),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn serve(server: &Server<Router<()>>, input: &str) -> String {
+        let mut stream = MockStream::new(input);
+        server
+            .serve_connection(&mut stream)
+            .expect("connection served");
+        String::from_utf8(stream.output).unwrap()
+    }
+
+    fn app() -> Router<()> {
+        Router::default()
+            .get("/first", || "first-body")
+            .get("/second", || "second-body")
+            .post("/echo", |body: String| body)
+    }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let server = Server::new("", 0).with_service(app());
+
+        let output = serve(
+            &server,
+            "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n\
+             POST /echo HTTP/1.1\r\nContent-Length: 9\r\n\r\necho-body\
+             GET /second HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+
+        let first = output.find("first-body").expect("first response");
+        let echo = output.find("echo-body").expect("echo response");
+        let second = output.find("second-body").expect("second response");
+        assert!(first < echo && echo < second);
+    }
+
+    #[test]
+    fn test_connection_close() {
+        let server = Server::new("", 0).with_service(app());
+
+        let output = serve(
+            &server,
+            "GET /first HTTP/1.1\r\nConnection: close\r\n\r\n\
+             GET /second HTTP/1.1\r\n\r\n",
+        );
+        assert!(output.contains("first-body"));
+        assert!(!output.contains("second-body"));
+
+        // HTTP/1.0 closes connection by default.
+        let output = serve(
+            &server,
+            "GET /first HTTP/1.0\r\n\r\nGET /second HTTP/1.0\r\n\r\n",
+        );
+        assert!(output.contains("first-body"));
+        assert!(!output.contains("second-body"));
+
+        let output = serve(
+            &server,
+            "GET /first HTTP/1.0\r\nConnection: keep-alive\r\n\r\n\
+             GET /second HTTP/1.0\r\n\r\n",
+        );
+        assert!(output.contains("first-body"));
+        assert!(output.contains("second-body"));
+    }
+
+    #[test]
+    fn test_http10_body_of_unknown_length() {
+        fn stream() -> crate::response::Response {
+            let (mut sender, body) = hyper::Body::channel();
+            std::thread::spawn(move || {
+                futures_executor::block_on(sender.send_data("streamed-body".into()))
+            });
+            crate::response::Response::new(body)
+        }
+
+        let server = Server::new("", 0).with_service(app().get("/stream", stream));
+
+        // Body ends with the connection, so it can't be kept alive.
+        let output = serve(
+            &server,
+            "GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n\
+             GET /second HTTP/1.0\r\n\r\n",
+        );
+        assert!(output.contains("connection: close\r\n"));
+        assert!(!output.contains("transfer-encoding"));
+        assert!(output.ends_with("\r\n\r\nstreamed-body"));
+    }
+
+    #[test]
+    fn test_head_requests() {
+        fn stream() -> crate::response::Response {
+            let (mut sender, body) = hyper::Body::channel();
+            std::thread::spawn(move || {
+                futures_executor::block_on(sender.send_data("streamed-body".into()))
+            });
+            crate::response::Response::new(body)
+        }
+
+        let server = Server::new("", 0).with_service(
+            app()
+                .get("/explicit", || "get-body")
+                .head("/explicit", || "explicit-body")
+                .any("/any", || "any-body")
+                .get("/stream", stream),
+        );
+
+        let output = serve(
+            &server,
+            "HEAD /explicit HTTP/1.1\r\n\r\n\
+             HEAD /any HTTP/1.1\r\n\r\n\
+             HEAD /first HTTP/1.1\r\n\r\n\
+             HEAD /missing HTTP/1.1\r\n\r\n\
+             HEAD /stream HTTP/1.1\r\n\r\n\
+             GET /second HTTP/1.1\r\n\r\n",
+        );
+
+        // Responses to HEAD end with their headers, connection stays in sync.
+        let responses: Vec<&str> = output.split("HTTP/1.1 ").skip(1).collect();
+        assert_eq!(responses.len(), 6);
+        for response in &responses[..5] {
+            assert!(response.ends_with("\r\n\r\n"), "response: {}", response);
+        }
+        assert!(responses[0].contains("content-length: 13\r\n"));
+        assert!(responses[1].contains("content-length: 8\r\n"));
+        assert!(responses[2].contains("content-length: 10\r\n"));
+        assert!(responses[3].starts_with("404 Not Found\r\n"));
+        assert!(!responses[4].contains("content-length"));
+        assert!(responses[5].ends_with("\r\n\r\nsecond-body"));
+    }
+
+    #[test]
+    fn test_max_requests_per_connection() {
+        let server = Server::new("", 0)
+            .with_service(app())
+            .max_requests_per_connection(Some(1));
+
+        let output = serve(
+            &server,
+            "GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n",
+        );
+        assert!(output.contains("first-body"));
+        assert!(!output.contains("second-body"));
+    }
+
+    #[test]
+    fn test_invalid_request_closes_connection() {
+        let server = Server::new("", 0).with_service(app()).max_body_size(4);
+
+        let output = serve(
+            &server,
+            "POST /echo HTTP/1.1\r\nContent-Length: 9\r\n\r\necho-body\
+             GET /first HTTP/1.1\r\n\r\n",
+        );
+        assert!(output.contains("413"));
+        assert!(!output.contains("first-body"));
+
+        let output = serve(&server, "GET /first HTTP/1.1\r\nBad Header\r\n\r\n");
+        assert!(output.contains("400"));
+    }
+
+    /// Encodes body in chunks of `chunk_size` bytes.
+    fn chunked(body: &str, chunk_size: usize) -> String {
+        let mut encoded = String::new();
+        for chunk in body.as_bytes().chunks(chunk_size) {
+            encoded.push_str(&format!("{:x}\r\n", chunk.len()));
+            encoded.push_str(std::str::from_utf8(chunk).unwrap());
+            encoded.push_str("\r\n");
+        }
+        encoded + "0\r\n\r\n"
+    }
+
+    #[test]
+    fn test_streamed_body() {
+        async fn count(mut body: BodyStream) -> Result<String, Rejection> {
+            let mut size = 0;
+            while let Some(chunk) = body.chunk().await? {
+                size += chunk.len();
+            }
+            Ok(size.to_string())
+        }
+
+        // Server has no limit of its own, route's limit applies.
+        let size = 11 * 1024 * 1024;
+        let server = Server::new("", 0).with_service(
+            app()
+                .body_limit(size)
+                .post("/count", count)
+                .post("/ignore", || "ignored"),
+        );
+
+        let output = serve(
+            &server,
+            &format!(
+                "POST /count HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}\
+                 GET /second HTTP/1.1\r\n\r\n",
+                chunked(&"a".repeat(size), 64 * 1024)
+            ),
+        );
+        assert!(output.contains(&format!("\r\n\r\n{}", size)));
+        assert!(output.ends_with("second-body"));
+
+        // Body that handler didn't read is skipped, unless there is too much of it.
+        let request = |size: usize| {
+            format!(
+                "POST /ignore HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}\
+                 GET /second HTTP/1.1\r\n\r\n",
+                size,
+                "a".repeat(size)
+            )
+        };
+        let output = serve(&server, &request(4 * 1024));
+        assert!(output.contains("ignored"));
+        assert!(output.ends_with("second-body"));
+
+        let output = serve(&server, &request(1024 * 1024));
+        assert!(output.contains("connection: close\r\n"));
+        assert!(output.ends_with("ignored"));
+
+        // Server's limit applies to bodies of unknown size as they arrive.
+        let server = Server::new("", 0)
+            .with_service(app().post("/count", count))
+            .max_body_size(1024);
+        let output = serve(
+            &server,
+            &format!(
+                "POST /count HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}\
+                 GET /second HTTP/1.1\r\n\r\n",
+                chunked(&"a".repeat(2048), 512)
+            ),
+        );
+        assert!(output.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert!(!output.contains("second-body"));
+    }
+
+    #[test]
+    fn test_body_is_passed_to_handler_as_it_arrives() {
+        use hyper::body::HttpBody;
+
+        let (chunk_tx, chunk_rx) = mpsc::channel();
+        let chunk_tx = Mutex::new(chunk_tx);
+        let stream = move |request: hyper::Request<hyper::Body>| {
+            let mut body = request.into_body();
+            let first = futures_executor::block_on(body.data()).unwrap().unwrap();
+            chunk_tx.lock().unwrap().send(first.clone()).unwrap();
+            let rest = crate::response::body_to_bytes(body).unwrap();
+            format!("{}+{}", first.len(), rest.len())
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0).with_service(app().post("/stream", stream));
+        std::thread::spawn(move || server.serve(listener));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        client
+            .write_all(
+                b"POST /stream HTTP/1.1\r\nContent-Length: 8\r\nConnection: close\r\n\r\nabcd",
+            )
+            .unwrap();
+
+        // Handler got the first part before the rest of the body was sent.
+        let first = chunk_rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("first part of the body");
+        assert_eq!(first, "abcd");
+
+        client.write_all(b"efgh").unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+        assert!(output.ends_with("4+4"));
+    }
+
+    #[test]
+    fn test_reject_when_workers_busy() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+
+        let slow = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+            "slow-body"
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0)
+            .with_service(app().get("/slow", slow))
+            .workers(1)
+            .queue_depth(1);
+        std::thread::spawn(move || server.serve(listener));
+
+        let connect = || {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+            stream
+        };
+
+        // First connection occupies the only worker, second one waits in the queue.
+        let mut busy = connect();
+        busy.write_all(b"GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        started_rx.recv().unwrap();
+        let mut queued = connect();
+        queued
+            .write_all(b"GET /first HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut output = String::new();
+        connect().read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+        release_tx.send(()).unwrap();
+        let mut output = String::new();
+        busy.read_to_string(&mut output).unwrap();
+        assert!(output.contains("slow-body"));
+
+        let mut output = String::new();
+        queued.read_to_string(&mut output).unwrap();
+        assert!(output.contains("first-body"));
+    }
+
+    /// Reads from the stream until `expected` shows up.
+    fn read_until(stream: &mut TcpStream, expected: &str) -> String {
+        let mut output = String::new();
+        let mut buf = [0u8; 1024];
+        while !output.contains(expected) {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed before {:?}", expected);
+            output.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+        }
+        output
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+
+        let slow = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+            "slow-body"
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0).with_service(app().get("/slow", slow));
+        let handle = server.shutdown_handle();
+        let running = std::thread::spawn(move || server.serve(listener));
+
+        let connect = || {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+            stream
+        };
+
+        let mut idle = connect();
+        idle.write_all(b"GET /first HTTP/1.1\r\n\r\n").unwrap();
+        read_until(&mut idle, "first-body");
+
+        let mut busy = connect();
+        busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+        started_rx.recv().unwrap();
+
+        handle.shutdown();
+        assert!(handle.is_shutdown());
+
+        // Idle keep-alive connection gets closed without waiting for its timeout.
+        assert_eq!(idle.read(&mut [0u8; 16]).unwrap(), 0);
+
+        release_tx.send(()).unwrap();
+        let mut output = String::new();
+        busy.read_to_string(&mut output).unwrap();
+        assert!(output.contains("connection: close\r\n"));
+        assert!(output.contains("slow-body"));
+
+        running
+            .join()
+            .unwrap()
+            .expect("server stopped without error");
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+
+        let stuck = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0)
+            .with_service(app().get("/stuck", stuck))
+            .shutdown_timeout(std::time::Duration::from_millis(100));
+        let handle = server.shutdown_handle();
+        let running = std::thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /stuck HTTP/1.1\r\n\r\n").unwrap();
+        started_rx.recv().unwrap();
+
+        handle.shutdown();
+        running
+            .join()
+            .unwrap()
+            .expect("server stopped without error");
+        release_tx.send(()).unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async_shutdown_timeout() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let started_tx = Mutex::new(started_tx);
+
+        let stuck = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            std::future::pending::<()>()
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::new("", 0)
+            .with_service(app().get("/stuck", stuck))
+            .shutdown_timeout(std::time::Duration::from_millis(100));
+        let handle = server.shutdown_handle();
+        let running = runtime.spawn(server.serve_async(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(b"GET /stuck HTTP/1.1\r\n\r\n").unwrap();
+        started_rx.recv().unwrap();
+
+        handle.shutdown();
+        runtime
+            .block_on(running)
+            .unwrap()
+            .expect("server stopped without error");
+        // Connection still busy when the timeout passed is closed.
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async_server() {
+        async fn async_handler(body: String) -> String {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            format!("async-{}", body)
+        }
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::new("", 0).with_service(app().post("/async", async_handler));
+        let handle = server.shutdown_handle();
+        let running = runtime.spawn(server.serve_async(listener));
+
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"POST /async HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody\
+                  POST /async HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  2\r\nst\r\n4\r\nream\r\n0\r\n\r\n\
+                  GET /first HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+
+        let first = output.find("async-body").expect("async response");
+        let streamed = output.find("async-stream").expect("streamed body response");
+        let second = output.find("first-body").expect("second response");
+        assert!(first < streamed && streamed < second);
+
+        // Idle connection does not block shutdown.
+        let mut idle = std::net::TcpStream::connect(addr).unwrap();
+        idle.write_all(b"GET /first HTTP/1.1\r\n\r\n").unwrap();
+        read_until(&mut idle, "first-body");
+
+        handle.shutdown();
+        runtime
+            .block_on(running)
+            .unwrap()
+            .expect("server stopped without error");
+        assert_eq!(idle.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async_handlers_run_on_runtime() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+        let release_tx = Mutex::new(release_tx);
+
+        // Takes the only thread of the blocking pool until the async handler releases it.
+        let wait = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            let released = release_rx
+                .lock()
+                .unwrap()
+                .recv_timeout(std::time::Duration::from_secs(5));
+            format!("released-{}", released.is_ok())
+        };
+        let release = move || {
+            let sent = release_tx.lock().unwrap().send(());
+            async move { format!("sent-{}", sent.is_ok()) }
+        };
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .max_blocking_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let app = Router::default()
+            .middleware(crate::middleware::LogMiddleware {})
+            .get("/wait", wait)
+            .get("/release", release);
+        let server = Server::new("", 0).with_service(app);
+        let handle = server.shutdown_handle();
+        let running = runtime.spawn(server.serve_async(listener));
+
+        let mut waiting = TcpStream::connect(addr).unwrap();
+        waiting.write_all(b"GET /wait HTTP/1.1\r\n\r\n").unwrap();
+        started_rx.recv().unwrap();
+
+        let mut releasing = TcpStream::connect(addr).unwrap();
+        releasing
+            .write_all(b"GET /release HTTP/1.1\r\n\r\n")
+            .unwrap();
+        read_until(&mut releasing, "sent-true");
+        read_until(&mut waiting, "released-true");
+
+        handle.shutdown();
+        drop((waiting, releasing));
+        runtime
+            .block_on(running)
+            .unwrap()
+            .expect("server stopped without error");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_service_called_synchronously_from_async_handler() {
+        use crate::handler::Service;
+        use hyper::{Body, Request};
+
+        async fn inner() -> &'static str {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            "inner"
+        }
+
+        let inner = Router::default().get("/", inner);
+        let outer = move |request: Request<Body>| {
+            let inner = inner.clone();
+            async move {
+                let body = inner.call(request).into_body();
+                let body = hyper::body::to_bytes(body).await.unwrap();
+                format!("outer-{}", String::from_utf8_lossy(&body))
+            }
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::new("", 0).with_service(Router::default().get("/", outer));
+        let handle = server.shutdown_handle();
+        let running = runtime.spawn(server.serve_async(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{}", output);
+        assert!(output.ends_with("outer-inner"), "{}", output);
+
+        handle.shutdown();
+        runtime
+            .block_on(running)
+            .unwrap()
+            .expect("server stopped without error");
+    }
+
+    #[test]
+    fn test_accept_backoff() {
+        use super::{accept_backoff, ACCEPT_BACKOFF};
+        use std::{io::ErrorKind, time::Duration};
+
+        let aborted = std::io::Error::from(ErrorKind::ConnectionAborted);
+        assert_eq!(accept_backoff(aborted).unwrap(), Duration::ZERO);
+        // EMFILE, too many open files.
+        let exhausted = std::io::Error::from_raw_os_error(24);
+        assert_eq!(accept_backoff(exhausted).unwrap(), ACCEPT_BACKOFF);
+        assert!(accept_backoff(ErrorKind::InvalidInput.into()).is_err());
+    }
 
     #[test]
     fn test_should_fire_on_path() {
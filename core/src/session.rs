@@ -0,0 +1,568 @@
+use crate::{
+    cookies::{Cookie, SameSite},
+    error::internal_error,
+    headers::{self, Header},
+    middleware::{AroundMiddleware, Next},
+    request::{FromRequestParts, Rejection},
+    response::Response,
+};
+use anyhow::{anyhow, bail};
+use hyper::{header::SET_COOKIE, http::request::Parts, Body, Request};
+use log::warn;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Default name of the cookie with session's ID.
+pub const DEFAULT_COOKIE_NAME: &str = "rhttp.sid";
+
+/// Default time session lives after the last request that used it.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Length of base64 encoded 32 random bytes.
+const ID_LEN: usize = 43;
+
+/// Session's data as kept in the store.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub data: HashMap<String, serde_json::Value>,
+
+    /// Unix timestamp in seconds after which session is no longer valid.
+    pub expires_at: u64,
+}
+
+impl SessionRecord {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_now()
+    }
+}
+
+/// Storage of sessions' data keyed by their IDs. Stores are called from
+/// middleware after the handler, for sessions that changed or whose expiry
+/// has to be extended only.
+pub trait SessionStore: Send + Sync {
+    /// Returns session's record, `None` when there is no such session.
+    fn load(&self, id: &str) -> anyhow::Result<Option<SessionRecord>>;
+
+    /// Creates or replaces session's record.
+    fn save(&self, id: &str, record: &SessionRecord) -> anyhow::Result<()>;
+
+    fn delete(&self, id: &str) -> anyhow::Result<()>;
+
+    /// Removes expired sessions, meant to be called periodically.
+    /// Expired session is never loaded, even if it's still stored.
+    fn remove_expired(&self) -> anyhow::Result<()>;
+}
+
+/// Store that keeps sessions in memory, they are lost on restart.
+/// Clones share the same sessions.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, SessionRecord>>>,
+}
+
+impl MemoryStore {
+    fn sessions(&self) -> anyhow::Result<MutexGuard<'_, HashMap<String, SessionRecord>>> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow!("session store lock is poisoned"))
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> anyhow::Result<Option<SessionRecord>> {
+        Ok(self.sessions()?.get(id).cloned())
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord) -> anyhow::Result<()> {
+        self.sessions()?.insert(id.to_string(), record.clone());
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> anyhow::Result<()> {
+        self.sessions()?.remove(id);
+        Ok(())
+    }
+
+    fn remove_expired(&self) -> anyhow::Result<()> {
+        self.sessions()?.retain(|_, record| !record.is_expired());
+        Ok(())
+    }
+}
+
+/// Store that keeps every session as JSON file in the directory.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Creates store in the directory, creating the directory if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// IDs are checked before they are used as file names,
+    /// so client can't make store touch other files.
+    fn path(&self, id: &str) -> anyhow::Result<PathBuf> {
+        if !is_valid_id(id) {
+            bail!("invalid session id");
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> anyhow::Result<Option<SessionRecord>> {
+        match fs::read(self.path(id)?) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record is written to temporary file first, so concurrent loads
+    /// never see it half-written.
+    fn save(&self, id: &str, record: &SessionRecord) -> anyhow::Result<()> {
+        let path = self.path(id)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(record)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> anyhow::Result<()> {
+        match fs::remove_file(self.path(id)?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_expired(&self) -> anyhow::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            let expired = fs::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<SessionRecord>(&data).ok())
+                .is_none_or(|record| record.is_expired());
+            if expired {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Middleware that loads session by ID from its cookie and passes it to handlers
+/// as `Session` extractor. After the handler, changed session is saved back
+/// to the store and cookie is sent to client. Store's failures answer request
+/// with 500.
+///
+/// ```rust
+/// use core::route::Router;
+/// use core::session::{MemoryStore, Session, SessionMiddleware};
+/// use std::time::Duration;
+///
+/// fn login(session: Session) -> anyhow::Result<()> {
+///     session.rotate_id();
+///     session.insert("user", "john")
+/// }
+///
+/// fn user(session: Session) -> String {
+///     session.get("user").unwrap_or_else(|| "anonymous".to_string())
+/// }
+///
+/// let sessions = SessionMiddleware::new(MemoryStore::default())
+///     .ttl(Duration::from_secs(3600))
+///     .secure(true);
+///
+/// let app = Router::default()
+///     .middleware(sessions)
+///     .post("/login", login)
+///     .get("/user", user);
+/// ```
+pub struct SessionMiddleware<St> {
+    store: St,
+    ttl: Duration,
+
+    /// Template of session's cookie, value is replaced by session's ID.
+    cookie: Cookie,
+}
+
+impl<St> SessionMiddleware<St>
+where
+    St: SessionStore,
+{
+    /// Creates middleware with the store. Session cookie is `HttpOnly`,
+    /// with `SameSite=Lax` and path `/`.
+    pub fn new(store: St) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_SESSION_TTL,
+            cookie: Cookie::named(DEFAULT_COOKIE_NAME)
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax),
+        }
+    }
+
+    /// Sets name of the cookie, `DEFAULT_COOKIE_NAME` by default.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie = self.cookie.with_name(name.into());
+        self
+    }
+
+    /// Sets how long session lives after the last request that used it,
+    /// `DEFAULT_SESSION_TTL` by default. It's also cookie's `Max-Age`.
+    /// Unchanged session is saved again, with its cookie, once less than
+    /// half of the time is left, so reading it doesn't write to the store
+    /// on every request.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Marks cookie as `Secure`, i.e. sent over HTTPS only.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.cookie = self.cookie.secure(secure);
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.cookie = self.cookie.same_site(same_site);
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.cookie = self.cookie.path(path);
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.cookie = self.cookie.domain(domain);
+        self
+    }
+
+    /// Returns session with data of the one from request's cookie,
+    /// new empty session when there is none or it expired.
+    fn load(&self, req: &Request<Body>) -> anyhow::Result<Session> {
+        let id = headers::Cookie::try_from_header_map(req.headers())
+            .ok()
+            .and_then(|cookies| cookies.get(self.cookie.name()).map(str::to_string))
+            .filter(|id| is_valid_id(id));
+
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(Session::default()),
+        };
+
+        match self.store.load(&id)? {
+            Some(record) if !record.is_expired() => Ok(Session::new(id, record)),
+            Some(_) => {
+                self.store.delete(&id)?;
+                Ok(Session::default())
+            }
+            None => Ok(Session::default()),
+        }
+    }
+
+    /// Saves or deletes changed session, returns cookie that has to be sent.
+    fn store(&self, session: &Session) -> anyhow::Result<Option<Cookie>> {
+        let mut inner = session.lock();
+
+        if inner.destroyed {
+            return match inner.id.take() {
+                Some(id) => {
+                    self.store.delete(&id)?;
+                    Ok(Some(self.cookie.removal()))
+                }
+                None => Ok(None),
+            };
+        }
+
+        let refresh = inner.id.is_some() && inner.expires_at < unix_now() + self.ttl.as_secs() / 2;
+        if !inner.modified && !inner.rotate && !refresh {
+            return Ok(None);
+        }
+
+        if inner.rotate {
+            if let Some(old) = inner.id.take() {
+                self.store.delete(&old)?;
+            }
+        }
+        let id = match &inner.id {
+            Some(id) => id.clone(),
+            None => generate_id()?,
+        };
+
+        let record = SessionRecord {
+            data: inner.data.clone(),
+            expires_at: unix_now() + self.ttl.as_secs(),
+        };
+        self.store.save(&id, &record)?;
+
+        inner.id = Some(id.clone());
+        inner.expires_at = record.expires_at;
+        inner.modified = false;
+        inner.rotate = false;
+        Ok(Some(self.cookie.with_value(id).max_age(self.ttl)))
+    }
+}
+
+impl<St> AroundMiddleware for SessionMiddleware<St>
+where
+    St: SessionStore,
+{
+    fn call(&self, mut req: Request<Body>, next: Next<'_>) -> Response {
+        let session = match self.load(&req) {
+            Ok(session) => session,
+            Err(err) => return internal_error(err.context("could not load session")),
+        };
+        req.extensions_mut().insert(session.clone());
+
+        let mut response = next.run(req);
+
+        match self.store(&session) {
+            Ok(Some(cookie)) => match cookie.to_header_value() {
+                Ok(value) => {
+                    response.headers_mut().append(SET_COOKIE, value);
+                }
+                Err(e) => warn!("invalid session cookie: {}", e),
+            },
+            Ok(None) => {}
+            Err(err) => return internal_error(err.context("could not save session")),
+        }
+        response
+    }
+}
+
+/// Data of the session, shared between the handler and `SessionMiddleware`.
+/// Values are kept as JSON, so anything serializable can be stored.
+/// Request to route without the middleware is rejected with 500.
+#[derive(Debug, Clone, Default)]
+pub struct Session(Arc<Mutex<SessionInner>>);
+
+#[derive(Debug, Default)]
+struct SessionInner {
+    /// `None` for session that wasn't saved yet.
+    id: Option<String>,
+    data: HashMap<String, serde_json::Value>,
+
+    /// Unix timestamp of session's expiry in the store, 0 for new session.
+    expires_at: u64,
+    modified: bool,
+    rotate: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    /// Creates session loaded from the store.
+    fn new(id: String, record: SessionRecord) -> Self {
+        Self(Arc::new(Mutex::new(SessionInner {
+            id: Some(id),
+            data: record.data,
+            expires_at: record.expires_at,
+            ..Default::default()
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionInner> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns session's ID, `None` until new session is saved.
+    pub fn id(&self) -> Option<String> {
+        self.lock().id.clone()
+    }
+
+    /// Returns value stored under the key, `None` when there is none
+    /// or it can't be deserialized to `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.lock().data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) -> anyhow::Result<()> {
+        let value = serde_json::to_value(value)?;
+        let mut inner = self.lock();
+        inner.data.insert(key.into(), value);
+        inner.modified = true;
+        Ok(())
+    }
+
+    /// Removes value stored under the key, returns if there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut inner = self.lock();
+        let removed = inner.data.remove(key).is_some();
+        inner.modified |= removed;
+        removed
+    }
+
+    /// Removes all values, session itself is kept.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.modified |= !inner.data.is_empty();
+        inner.data.clear();
+    }
+
+    /// Gives session new ID, keeping its data. Should be called when user's
+    /// privileges change, e.g. on login, so old ID can't be used anymore.
+    pub fn rotate_id(&self) {
+        self.lock().rotate = true;
+    }
+
+    /// Deletes session from the store and its cookie from client, e.g. on logout.
+    pub fn destroy(&self) {
+        let mut inner = self.lock();
+        inner.data.clear();
+        inner.destroyed = true;
+    }
+}
+
+impl<S> FromRequestParts<S> for Session {
+    fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Rejection> {
+        parts
+            .extensions
+            .get::<Session>()
+            .cloned()
+            .ok_or_else(|| Rejection::internal("session middleware is not registered"))
+    }
+}
+
+/// Returns 32 random bytes as URL-safe base64.
+fn generate_id() -> anyhow::Result<String> {
+    let mut bytes = [0; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("system random generator failed"))?;
+    Ok(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        generate_id, is_valid_id, unix_now, FileStore, MemoryStore, Session, SessionMiddleware,
+        SessionRecord, SessionStore,
+    };
+    use crate::{handler::Service, route::Router};
+    use hyper::{header::SET_COOKIE, Body, Request};
+    use std::time::Duration;
+
+    fn check_store(store: &dyn SessionStore) {
+        let id = generate_id().unwrap();
+        assert!(is_valid_id(&id));
+        assert_eq!(store.load(&id).unwrap(), None);
+
+        let mut record = SessionRecord {
+            expires_at: u64::MAX,
+            ..Default::default()
+        };
+        record.data.insert("user".into(), "john".into());
+        store.save(&id, &record).unwrap();
+        assert_eq!(store.load(&id).unwrap(), Some(record.clone()));
+
+        let expired_id = generate_id().unwrap();
+        let expired = SessionRecord {
+            expires_at: 1,
+            ..Default::default()
+        };
+        store.save(&expired_id, &expired).unwrap();
+        store.remove_expired().unwrap();
+        assert_eq!(store.load(&expired_id).unwrap(), None);
+        assert_eq!(store.load(&id).unwrap(), Some(record));
+
+        store.delete(&id).unwrap();
+        store.delete(&id).unwrap();
+        assert_eq!(store.load(&id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&MemoryStore::default());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("rhttp-sessions-{}", std::process::id()));
+        let store = FileStore::new(&dir).unwrap();
+        check_store(&store);
+
+        assert!(store.load("../../etc/passwd").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_session_expiry_is_extended_when_used() {
+        fn user(session: Session) -> String {
+            session.get("user").unwrap_or_default()
+        }
+
+        let store = MemoryStore::default();
+        let ttl = Duration::from_secs(3600);
+        let app = Router::default()
+            .middleware(SessionMiddleware::new(store.clone()).ttl(ttl))
+            .get("/", user);
+
+        let save = |expires_at| {
+            let id = generate_id().unwrap();
+            let mut record = SessionRecord {
+                expires_at,
+                ..Default::default()
+            };
+            record.data.insert("user".into(), "john".into());
+            store.save(&id, &record).unwrap();
+            id
+        };
+        let get = |id: &str| {
+            let request = Request::builder()
+                .uri("/")
+                .header("cookie", format!("rhttp.sid={}", id))
+                .body(Body::empty())
+                .unwrap();
+            app.call(request)
+        };
+
+        // Session with most of its TTL left is not saved again.
+        let fresh = save(unix_now() + ttl.as_secs());
+        assert!(!get(&fresh).headers().contains_key(SET_COOKIE));
+
+        // Session past half of its TTL gets new expiry and cookie.
+        let stale = save(unix_now() + 10);
+        let response = get(&stale);
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with(&format!("rhttp.sid={};", stale)));
+        assert!(cookie.contains("Max-Age=3600"));
+        let record = store.load(&stale).unwrap().unwrap();
+        assert!(record.expires_at >= unix_now() + ttl.as_secs() - 1);
+        assert_eq!(record.data["user"], "john");
+    }
+}
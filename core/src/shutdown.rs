@@ -0,0 +1,103 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Handle that stops running `core::server::Server`. It can be cloned
+/// and moved to other threads, e.g. to a signal handler or a test.
+///
+/// After `shutdown` is called server stops accepting new connections,
+/// lets in-flight requests finish within server's shutdown timeout
+/// and returns from `run`.
+///
+/// ```no_run
+/// use core::route::Router;
+/// use core::server::Server;
+///
+/// let server = Server::new("127.0.0.1", 8080).with_service(Router::default());
+/// let handle = server.shutdown_handle();
+///
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(10));
+///     handle.shutdown();
+/// });
+///
+/// server.run().unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    requested: AtomicBool,
+
+    /// Address server listens on, used to wake up blocking accept.
+    local_addr: Mutex<Option<SocketAddr>>,
+
+    /// Notifies tasks of async server.
+    #[cfg(feature = "tokio")]
+    signal: tokio::sync::watch::Sender<bool>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            local_addr: Mutex::new(None),
+            #[cfg(feature = "tokio")]
+            signal: tokio::sync::watch::channel(false).0,
+        }
+    }
+}
+
+impl ShutdownHandle {
+    /// Requests server shutdown. Returns immediately, without waiting for the server.
+    pub fn shutdown(&self) {
+        if self.inner.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        #[cfg(feature = "tokio")]
+        self.inner.signal.send_replace(true);
+
+        // Blocking accept returns only when a connection comes, so we make one.
+        let local_addr = *self.inner.local_addr.lock().unwrap();
+        if let Some(addr) = local_addr {
+            let _ = TcpStream::connect(connectable(addr));
+        }
+    }
+
+    /// Indicates if shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Completes when shutdown is requested.
+    #[cfg(feature = "tokio")]
+    pub async fn wait(&self) {
+        let mut receiver = self.inner.signal.subscribe();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Registers address of the listener that should be woken up on shutdown.
+    pub(crate) fn set_local_addr(&self, addr: SocketAddr) {
+        *self.inner.local_addr.lock().unwrap() = Some(addr);
+    }
+}
+
+/// Listener bound to unspecified address accepts connections on loopback.
+fn connectable(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, addr.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, addr.port()).into(),
+        _ => addr,
+    }
+}
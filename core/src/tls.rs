@@ -0,0 +1,349 @@
+use anyhow::{bail, Context};
+use rustls::{
+    server::{
+        AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientHello,
+        ResolvesServerCert,
+    },
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, sync::Arc};
+
+/// TLS settings of `core::server::Server`: certificates it presents
+/// and verification of client certificates.
+///
+/// ```no_run
+/// use core::route::Router;
+/// use core::server::Server;
+/// use core::tls::{ClientAuth, TlsConfig};
+///
+/// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")
+///     .and_then(|tls| tls.with_sni_cert("api.example.com", "api-cert.pem", "api-key.pem"))
+///     .and_then(|tls| tls.with_client_auth("clients-ca.pem", ClientAuth::Required))
+///     .unwrap();
+///
+/// Server::new("0.0.0.0", 8443)
+///     .with_service(Router::default())
+///     .with_tls_config(tls)
+///     .unwrap()
+///     .run()
+///     .unwrap();
+/// ```
+pub struct TlsConfig {
+    /// Certificate used when client sent no SNI or an unknown hostname.
+    default: Arc<CertifiedKey>,
+
+    /// Certificates selected by SNI hostname, hostnames are lowercase.
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+
+    /// Trusted issuers of client certificates, `None` disables client authentication.
+    client_roots: Option<(RootCertStore, ClientAuth)>,
+}
+
+/// Describes if clients have to present a certificate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Clients without certificate are accepted, presented ones are verified.
+    Optional,
+
+    /// Handshake fails unless client presents a valid certificate.
+    Required,
+}
+
+impl TlsConfig {
+    /// Loads certificate chain and its private key from PEM files. Chain starts
+    /// with server's certificate, key can be PKCS#8, PKCS#1 (RSA) or SEC1 (EC) one.
+    pub fn from_pem_files(
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            default: load_certified_key(cert_chain.as_ref(), private_key.as_ref())?,
+            by_name: HashMap::new(),
+            client_roots: None,
+        })
+    }
+
+    /// Adds certificate presented to clients that ask for `hostname` with SNI.
+    pub fn with_sni_cert(
+        mut self,
+        hostname: &str,
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let key = load_certified_key(cert_chain.as_ref(), private_key.as_ref())?;
+        self.by_name.insert(hostname.to_ascii_lowercase(), key);
+        Ok(self)
+    }
+
+    /// Enables verification of client certificates issued by CAs from the PEM file.
+    pub fn with_client_auth(
+        mut self,
+        ca_certs: impl AsRef<Path>,
+        auth: ClientAuth,
+    ) -> anyhow::Result<Self> {
+        let path = ca_certs.as_ref();
+        let mut roots = RootCertStore::empty();
+        let (added, _) = roots.add_parsable_certificates(&load_certs(path)?);
+        if added == 0 {
+            bail!("no valid CA certificates in {}", path.display());
+        }
+
+        self.client_roots = Some((roots, auth));
+        Ok(self)
+    }
+
+    /// Builds rustls configuration used by connections.
+    pub(crate) fn into_server_config(self) -> anyhow::Result<Arc<ServerConfig>> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match self.client_roots {
+            None => builder.with_no_client_auth(),
+            Some((roots, ClientAuth::Optional)) => builder
+                .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots)),
+            Some((roots, ClientAuth::Required)) => {
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            }
+        };
+
+        let mut config = builder.with_cert_resolver(Arc::new(CertResolver {
+            default: self.default,
+            by_name: self.by_name,
+        }));
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// Picks certificate by SNI hostname, falls back to the default one.
+struct CertResolver {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| self.by_name.get(&name.to_ascii_lowercase()))
+            .unwrap_or(&self.default);
+
+        Some(key.clone())
+    }
+}
+
+fn load_certified_key(cert_chain: &Path, private_key: &Path) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certs = load_certs(cert_chain)?;
+    if certs.is_empty() {
+        bail!("no certificates in {}", cert_chain.display());
+    }
+
+    let key = load_private_key(private_key)?;
+    let key = sign::any_supported_type(&key)
+        .with_context(|| format!("unsupported private key in {}", private_key.display()))?;
+
+    Ok(Arc::new(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    )))
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let file = File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("invalid PEM file {}", path.display()))?;
+
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    let file = File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader)
+            .with_context(|| format!("invalid PEM file {}", path.display()))?;
+
+        match item {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => bail!("no private key in {}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientAuth, TlsConfig};
+    use crate::route::Router;
+    use crate::server::Server;
+    use rcgen::{BasicConstraints, Certificate as Generated, CertificateParams, IsCa};
+    use rustls::{
+        Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, StreamOwned,
+    };
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        path::{Path, PathBuf},
+        sync::{mpsc, Arc, Mutex},
+    };
+
+    /// Writes self-signed certificate and its key as PEM files into a temporary directory.
+    fn write_pem(dir: &str, name: &str, cert: &Generated) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rhttp-tls-{}-{}", std::process::id(), dir));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let cert_path = dir.join(format!("{}.pem", name));
+        let key_path = dir.join(format!("{}-key.pem", name));
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn start(tls: TlsConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0)
+            .with_service(Router::default().get("/", || "secure"))
+            .with_tls_config(tls)
+            .unwrap();
+        std::thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    /// Sends request over TLS, returns whole response and certificate presented by server.
+    fn get(
+        addr: SocketAddr,
+        hostname: &str,
+        config: ClientConfig,
+    ) -> std::io::Result<(String, Certificate)> {
+        let name = hostname.try_into().unwrap();
+        let connection = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut stream = StreamOwned::new(connection, TcpStream::connect(addr)?);
+
+        stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")?;
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+
+        let cert = stream.conn.peer_certificates().unwrap()[0].clone();
+        Ok((output, cert))
+    }
+
+    /// Reads certificate back from PEM file, serializing it again would give another signature.
+    fn der(path: &Path) -> Certificate {
+        Certificate(super::load_certs(path).unwrap().remove(0))
+    }
+
+    fn trusting(
+        certs: &[&Path],
+    ) -> rustls::ConfigBuilder<ClientConfig, rustls::client::WantsTransparencyPolicyOrClientCert>
+    {
+        let mut roots = RootCertStore::empty();
+        for cert in certs {
+            roots.add(&der(cert)).unwrap();
+        }
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+    }
+
+    #[test]
+    fn test_https_with_sni() {
+        let default = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let other = rcgen::generate_simple_self_signed(vec!["other.test".into()]).unwrap();
+        let (cert, key) = write_pem("sni", "default", &default);
+        let (other_cert, other_key) = write_pem("sni", "other", &other);
+
+        let tls = TlsConfig::from_pem_files(&cert, key)
+            .unwrap()
+            .with_sni_cert("Other.Test", &other_cert, other_key)
+            .unwrap();
+        let addr = start(tls);
+
+        let client = || trusting(&[&cert, &other_cert]).with_no_client_auth();
+
+        let (output, presented) = get(addr, "localhost", client()).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("secure"));
+        assert_eq!(presented, der(&cert));
+
+        let (output, presented) = get(addr, "other.test", client()).unwrap();
+        assert!(output.ends_with("secure"));
+        assert_eq!(presented, der(&other_cert));
+    }
+
+    #[test]
+    fn test_client_auth() {
+        let server_cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let (cert, key) = write_pem("mtls", "server", &server_cert);
+
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = Generated::from_params(params).unwrap();
+        let (ca_path, _) = write_pem("mtls", "ca", &ca);
+
+        let tls = TlsConfig::from_pem_files(&cert, key)
+            .unwrap()
+            .with_client_auth(ca_path, ClientAuth::Required)
+            .unwrap();
+        let addr = start(tls);
+
+        let client = Generated::from_params(CertificateParams::new(vec!["client".into()])).unwrap();
+        let client_config = trusting(&[&cert])
+            .with_single_cert(
+                vec![Certificate(client.serialize_der_with_signer(&ca).unwrap())],
+                PrivateKey(client.serialize_private_key_der()),
+            )
+            .unwrap();
+
+        let (output, _) = get(addr, "localhost", client_config).unwrap();
+        assert!(output.ends_with("secure"));
+
+        let anonymous = trusting(&[&cert]).with_no_client_auth();
+        assert!(get(addr, "localhost", anonymous).is_err());
+    }
+
+    #[test]
+    fn test_reject_when_workers_busy() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+
+        let slow = move || {
+            started_tx.lock().unwrap().send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+            "slow"
+        };
+
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let (cert, key) = write_pem("reject", "server", &generated);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new("", 0)
+            .with_service(Router::default().get("/", slow))
+            .with_tls_config(TlsConfig::from_pem_files(&cert, key).unwrap())
+            .unwrap()
+            .workers(1)
+            .queue_depth(1);
+        std::thread::spawn(move || server.serve(listener));
+
+        // First connection occupies the only worker, second one waits in the queue.
+        let client = trusting(&[&cert]).with_no_client_auth();
+        let busy = std::thread::spawn(move || get(addr, "localhost", client));
+        started_rx.recv().unwrap();
+        let _queued = TcpStream::connect(addr).unwrap();
+
+        // TLS connection over the limit is closed without handshake or response.
+        let mut rejected = TcpStream::connect(addr).unwrap();
+        rejected
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(rejected.read(&mut [0u8; 16]).unwrap(), 0);
+
+        release_tx.send(()).unwrap();
+        let (output, _) = busy.join().unwrap().unwrap();
+        assert!(output.ends_with("slow"));
+    }
+}
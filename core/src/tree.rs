@@ -0,0 +1,535 @@
+use anyhow::{bail, Context};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Prefix tree of routes' paths, built segment by segment. Looking up a path
+/// takes time proportional to its length rather than to number of routes.
+///
+/// Segments of a path can be:
+/// - literal ones, e.g. `users`,
+/// - `<name>` params matching any non-empty segment,
+/// - `<name:u32>` or `<name:[a-z-]+>` params matching only values that parse
+///   as the given number type or match the whole regex, regex can't contain `/`,
+/// - `<name?>` (or `<name?:u32>`) optional params, allowed only at the end of path,
+/// - `<name..>` catch-all param that takes the rest of path, allowed only as the last one.
+///
+/// Static segments take precedence over params regardless of registration order,
+/// so `/users/me` is matched before `/users/<id>`. Constrained params are tried
+/// before unconstrained ones and the catch-all is tried last. When a branch
+/// does not match the rest of the path, the next one is tried.
+///
+/// ```
+/// use core::tree::Tree;
+///
+/// let mut tree = Tree::default();
+/// tree.insert("/users/<id>", "user").unwrap();
+/// tree.insert("/users/me", "me").unwrap();
+/// tree.insert("/files/<path..>", "file").unwrap();
+///
+/// assert_eq!(*tree.at("/users/me").unwrap().value, "me");
+///
+/// let matched = tree.at("/users/10").unwrap();
+/// assert_eq!(*matched.value, "user");
+/// assert_eq!(matched.params, vec![("id", "10")]);
+///
+/// let matched = tree.at("/files/css/main.css").unwrap();
+/// assert_eq!(matched.params, vec![("path", "css/main.css")]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tree<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    /// Value of route that ends at this node, together with its path.
+    value: Option<(String, T)>,
+
+    /// Children for literal segments.
+    statics: HashMap<String, Node<T>>,
+
+    /// Children for `<param>` segments, constrained ones come first.
+    params: Vec<ParamNode<T>>,
+
+    /// Child for `<param..>` segment with param's name.
+    catch_all: Option<(String, Box<Node<T>>)>,
+}
+
+#[derive(Debug, Clone)]
+struct ParamNode<T> {
+    name: String,
+    constraint: Option<Constraint>,
+    node: Node<T>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            statics: HashMap::new(),
+            params: vec![],
+            catch_all: None,
+        }
+    }
+}
+
+/// Route found for a path.
+#[derive(Debug)]
+pub struct Match<'t, 'p, T> {
+    pub value: &'t T,
+
+    /// Names of path's params with their raw values, in order of appearance.
+    pub params: Vec<(&'t str, &'p str)>,
+}
+
+impl<T: Clone> Tree<T> {
+    /// Adds route. Fails when path is invalid, when the same path is already
+    /// registered or when param at the same position with the same constraint
+    /// has a different name in another route.
+    ///
+    /// Path with optional segments is added once for every number of them present.
+    pub fn insert(&mut self, path: &str, value: T) -> anyhow::Result<()> {
+        let segments = parse_path(path)?;
+        let required = segments
+            .iter()
+            .position(|segment| matches!(segment, Segment::Param { optional: true, .. }))
+            .unwrap_or(segments.len());
+
+        for len in required..=segments.len() {
+            self.insert_segments(path, &segments[..len], value.clone())?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Tree<T> {
+    fn insert_segments(
+        &mut self,
+        path: &str,
+        segments: &[Segment],
+        value: T,
+    ) -> anyhow::Result<()> {
+        let mut node = &mut self.root;
+
+        for segment in segments {
+            node = match segment {
+                Segment::Static(segment) => node.statics.entry(segment.to_string()).or_default(),
+                Segment::Param {
+                    name, constraint, ..
+                } => node.param_child(path, name, constraint)?,
+                Segment::CatchAll(name) => {
+                    let (existing, child) = node
+                        .catch_all
+                        .get_or_insert_with(|| (name.to_string(), Box::default()));
+                    if existing != name {
+                        bail!(
+                            "route {} conflicts with other routes: param <{}..> is already named <{}..>",
+                            path,
+                            name,
+                            existing
+                        );
+                    }
+                    child
+                }
+            };
+        }
+
+        if let Some((existing, _)) = &node.value {
+            bail!(
+                "route {} conflicts with already registered {}",
+                path,
+                existing
+            );
+        }
+        node.value = Some((path.to_string(), value));
+        Ok(())
+    }
+
+    /// Returns registered routes with paths they were inserted with.
+    /// Routes with optional segments are returned once.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        let mut routes = vec![];
+        self.root.collect(0, &mut routes);
+        routes.into_iter()
+    }
+
+    /// Finds route matching the path.
+    pub fn at<'t, 'p>(&'t self, path: &'p str) -> Option<Match<'t, 'p, T>> {
+        let mut params = vec![];
+        let value = self.root.find(Some(path), &mut params)?;
+
+        Some(Match { value, params })
+    }
+}
+
+impl<T> Node<T> {
+    /// Returns child for param with given constraint, creates it when missing.
+    fn param_child(
+        &mut self,
+        path: &str,
+        name: &str,
+        constraint: &Option<Constraint>,
+    ) -> anyhow::Result<&mut Node<T>> {
+        let source = constraint.as_ref().map(|c| c.source.as_str());
+        let index = match self
+            .params
+            .iter()
+            .position(|param| param.constraint.as_ref().map(|c| c.source.as_str()) == source)
+        {
+            Some(index) => index,
+            None => {
+                // Unconstrained param is kept last, so it's tried after constrained ones.
+                let index = match constraint {
+                    Some(_) => self
+                        .params
+                        .iter()
+                        .position(|param| param.constraint.is_none())
+                        .unwrap_or(self.params.len()),
+                    None => self.params.len(),
+                };
+                self.params.insert(
+                    index,
+                    ParamNode {
+                        name: name.to_string(),
+                        constraint: constraint.clone(),
+                        node: Node::default(),
+                    },
+                );
+                index
+            }
+        };
+
+        let param = &mut self.params[index];
+        if param.name != name {
+            bail!(
+                "route {} conflicts with other routes: param <{}> is already named <{}>",
+                path,
+                name,
+                param.name
+            );
+        }
+        Ok(&mut param.node)
+    }
+
+    /// Collects values of node and its descendants, `depth` is number of segments
+    /// leading to the node. Shorter variants of paths with optional segments are skipped.
+    fn collect<'t>(&'t self, depth: usize, routes: &mut Vec<(&'t str, &'t T)>) {
+        if let Some((path, value)) = &self.value {
+            if path.split('/').count() == depth {
+                routes.push((path, value));
+            }
+        }
+
+        let children = self
+            .statics
+            .values()
+            .chain(self.params.iter().map(|param| &param.node))
+            .chain(self.catch_all.iter().map(|(_, child)| child.as_ref()));
+        for child in children {
+            child.collect(depth + 1, routes);
+        }
+    }
+
+    /// Matches rest of the path, `None` when the whole path was consumed.
+    fn find<'t, 'p>(
+        &'t self,
+        path: Option<&'p str>,
+        params: &mut Vec<(&'t str, &'p str)>,
+    ) -> Option<&'t T> {
+        let path = match path {
+            Some(path) => path,
+            None => return self.value.as_ref().map(|(_, value)| value),
+        };
+        let (segment, rest) = match path.split_once('/') {
+            Some((segment, rest)) => (segment, Some(rest)),
+            None => (path, None),
+        };
+
+        if let Some(child) = self.statics.get(segment) {
+            if let Some(value) = child.find(rest, params) {
+                return Some(value);
+            }
+        }
+
+        if !segment.is_empty() {
+            for param in &self.params {
+                if !param.accepts(segment) {
+                    continue;
+                }
+                params.push((&param.name, segment));
+                if let Some(value) = param.node.find(rest, params) {
+                    return Some(value);
+                }
+                params.pop();
+            }
+        }
+
+        match &self.catch_all {
+            Some((name, child)) if !path.is_empty() => {
+                let (_, value) = child.value.as_ref()?;
+                params.push((name, path));
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T> ParamNode<T> {
+    /// Checks percent-decoded segment against param's constraint.
+    fn accepts(&self, segment: &str) -> bool {
+        match &self.constraint {
+            Some(constraint) => percent_decode_str(segment)
+                .decode_utf8()
+                .is_ok_and(|value| constraint.matches(&value)),
+            None => true,
+        }
+    }
+}
+
+/// Restriction of values a param matches.
+#[derive(Debug, Clone)]
+pub(crate) struct Constraint {
+    /// Constraint as written in the path, e.g. `u32`.
+    source: String,
+    kind: ConstraintKind,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ConstraintKind {
+    /// Value has to parse as a number type.
+    Parses(fn(&str) -> bool),
+
+    /// Value has to match the whole regex.
+    Regex(Regex),
+}
+
+impl Constraint {
+    fn parse(source: &str) -> anyhow::Result<Self> {
+        macro_rules! parses {
+            ($($ty:ident)*) => {
+                match source {
+                    $(stringify!($ty) => Some((|value| value.parse::<$ty>().is_ok()) as fn(&str) -> bool),)*
+                    _ => None,
+                }
+            };
+        }
+
+        let kind = match parses!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64) {
+            Some(parses) => ConstraintKind::Parses(parses),
+            None => ConstraintKind::Regex(Regex::new(&format!("^(?:{})$", source))?),
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            kind,
+        })
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match &self.kind {
+            ConstraintKind::Parses(parses) => parses(value),
+            ConstraintKind::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Segment of a route's path.
+#[derive(Debug)]
+pub(crate) enum Segment<'a> {
+    Static(&'a str),
+    Param {
+        name: &'a str,
+        constraint: Option<Constraint>,
+        optional: bool,
+    },
+    CatchAll(&'a str),
+}
+
+/// Splits route's path into segments, checks that optional params
+/// and catch-all are placed at the end.
+pub(crate) fn parse_path(path: &str) -> anyhow::Result<Vec<Segment<'_>>> {
+    let segments = path
+        .split('/')
+        .map(parse_segment)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .with_context(|| format!("invalid route {}", path))?;
+
+    let mut optional_found = false;
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Param { optional: true, .. } => optional_found = true,
+            Segment::CatchAll(name) if i + 1 != segments.len() => {
+                bail!(
+                    "invalid route {}: <{}..> has to be the last segment",
+                    path,
+                    name
+                )
+            }
+            _ if optional_found => {
+                bail!(
+                    "invalid route {}: only optional params can follow optional one",
+                    path
+                )
+            }
+            _ => {}
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_segment(segment: &str) -> anyhow::Result<Segment<'_>> {
+    let param = match segment.strip_prefix('<') {
+        Some(param) => param
+            .strip_suffix('>')
+            .context("Invalid url - param segment not closed")?,
+        None => return Ok(Segment::Static(segment)),
+    };
+
+    let (name, constraint) = match param.split_once(':') {
+        Some((name, constraint)) => (name, Some(constraint)),
+        None => (param, None),
+    };
+
+    if let Some(name) = name.strip_suffix("..") {
+        if constraint.is_some() {
+            bail!("catch-all param <{}..> can't have a constraint", name);
+        }
+        return Ok(Segment::CatchAll(check_name(name)?));
+    }
+
+    let (name, optional) = match name.strip_suffix('?') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let constraint = constraint
+        .map(Constraint::parse)
+        .transpose()
+        .with_context(|| format!("invalid constraint of param <{}>", name))?;
+
+    Ok(Segment::Param {
+        name: check_name(name)?,
+        constraint,
+        optional,
+    })
+}
+
+fn check_name(name: &str) -> anyhow::Result<&str> {
+    if name.is_empty() {
+        bail!("param's name can't be empty");
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tree;
+
+    #[test]
+    fn test_static_precedence() {
+        let mut tree = Tree::default();
+        tree.insert("/users/<id>/posts", 1).unwrap();
+        tree.insert("/users/me", 2).unwrap();
+        tree.insert("/users/me/settings", 3).unwrap();
+        tree.insert("/", 4).unwrap();
+
+        assert_eq!(*tree.at("/users/me").unwrap().value, 2);
+        assert_eq!(*tree.at("/users/me/settings").unwrap().value, 3);
+        assert_eq!(*tree.at("/").unwrap().value, 4);
+
+        // Static branch does not match the rest, param one does.
+        let matched = tree.at("/users/me/posts").unwrap();
+        assert_eq!(*matched.value, 1);
+        assert_eq!(matched.params, vec![("id", "me")]);
+
+        assert!(tree.at("/users").is_none());
+        assert!(tree.at("/users//posts").is_none());
+        assert!(tree.at("/users/1/posts/").is_none());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut tree = Tree::default();
+        tree.insert("/users/<id>", ()).unwrap();
+        tree.insert("/users/<id>/posts", ()).unwrap();
+        tree.insert("/users/<id:u32>/comments", ()).unwrap();
+        tree.insert("/posts/<page?>", ()).unwrap();
+
+        assert!(tree.insert("/users/<id>", ()).is_err());
+        assert!(tree.insert("/users/<name>/comments", ()).is_err());
+        assert!(tree.insert("/users/<user:u32>", ()).is_err());
+        assert!(tree.insert("/posts", ()).is_err());
+    }
+
+    #[test]
+    fn test_catch_all_and_optional() {
+        let mut tree = Tree::default();
+        tree.insert("/static/<path..>", 1).unwrap();
+        tree.insert("/static/index.html", 2).unwrap();
+        tree.insert("/posts/<page?>/<size?>", 3).unwrap();
+
+        let matched = tree.at("/static/css/main.css").unwrap();
+        assert_eq!(*matched.value, 1);
+        assert_eq!(matched.params, vec![("path", "css/main.css")]);
+        assert_eq!(*tree.at("/static/index.html").unwrap().value, 2);
+        assert!(tree.at("/static/").is_none());
+        assert!(tree.at("/static").is_none());
+
+        assert_eq!(tree.at("/posts").unwrap().params, vec![]);
+        assert_eq!(tree.at("/posts/2").unwrap().params, vec![("page", "2")]);
+        assert_eq!(
+            tree.at("/posts/2/10").unwrap().params,
+            vec![("page", "2"), ("size", "10")]
+        );
+        assert!(tree.at("/posts/2/10/1").is_none());
+
+        let mut routes: Vec<_> = tree.iter().collect();
+        routes.sort();
+        assert_eq!(
+            routes,
+            vec![
+                ("/posts/<page?>/<size?>", &3),
+                ("/static/<path..>", &1),
+                ("/static/index.html", &2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constraints() {
+        let mut tree = Tree::default();
+        tree.insert("/items/<slug>/edit", "any").unwrap();
+        tree.insert("/items/<id:u32>", "id").unwrap();
+        tree.insert("/items/<slug:[a-z-]+>", "slug").unwrap();
+        tree.insert("/tags/<tag:[a-zą]+>", "tag").unwrap();
+
+        assert_eq!(*tree.at("/items/42").unwrap().value, "id");
+        assert_eq!(*tree.at("/items/new-item").unwrap().value, "slug");
+        assert_eq!(*tree.at("/items/42/edit").unwrap().value, "any");
+        assert!(tree.at("/items/New").is_none());
+        assert!(tree.at("/items/99999999999").is_none());
+
+        // Constraints are checked against percent-decoded values.
+        assert_eq!(*tree.at("/tags/w%C4%85s").unwrap().value, "tag");
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        let mut tree = Tree::default();
+        assert!(tree.insert("/files/<path..>/raw", ()).is_err());
+        assert!(tree.insert("/files/<path..:u32>", ()).is_err());
+        assert!(tree.insert("/posts/<page?>/all", ()).is_err());
+        assert!(tree.insert("/items/<id:[a-z>", ()).is_err());
+        assert!(tree.insert("/items/<>", ()).is_err());
+        assert!(tree.insert("/items/<id", ()).is_err());
+    }
+}
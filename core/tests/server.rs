@@ -1,11 +1,23 @@
 use anyhow::Ok;
-use core::handler::HandlerTraitWithoutState;
-use core::request::{ContentType, Host, Json, PathParam, Query, State};
-use core::response::{Responder, Response};
-use core::route::{Route, RouteGroup, Router};
+use core::body::{BodyLimit, BodyStream, DEFAULT_BODY_LIMIT};
+use core::cookies::{Cookie, CookieJar, Key, PrivateCookieJar, SameSite, SignedCookieJar};
+use core::error::HttpError;
+use core::handler::{HandlerTraitWithoutState, Service};
+use core::headers::{Authorization, ByteRange, IfNoneMatch, Range, TypedHeader, UserAgent};
+use core::middleware::{from_fn, AroundMiddleware, Middleware, Next};
+use core::multipart::Multipart;
+use core::negotiate::Negotiate;
+use core::request::{
+    ContentType, Form, FromRef, Host, Json, Path, PathParam, Query, RawPathParams, Rejection, State,
+};
+use core::response::Response;
+use core::route::{AllowedMethods, MethodFilter, Route, RouteGroup, Router};
+use core::session::{MemoryStore, Session, SessionMiddleware};
 use hyper::Body;
-use hyper::{Method, StatusCode};
+use hyper::{Method, Request, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tools::TestCaseBuilder;
 
 mod tools;
@@ -41,14 +53,6 @@ struct OwnBody {
     val3: bool,
 }
 
-impl Responder for OwnBody {
-    fn into_response(self) -> anyhow::Result<Response> {
-        Ok(hyper::Response::builder()
-            .status(StatusCode::OK)
-            .body(Body::from(serde_json::to_string(&self)?))?)
-    }
-}
-
 #[test]
 fn test_with_client() -> anyhow::Result<()> {
     fn empty() {}
@@ -65,8 +69,8 @@ fn test_with_client() -> anyhow::Result<()> {
         Ok("ok")
     }
 
-    fn body_handler_json(Json(body): Json<OwnBody>) -> anyhow::Result<OwnBody> {
-        Ok(body)
+    fn body_handler_json(Json(body): Json<OwnBody>) -> anyhow::Result<Json<OwnBody>> {
+        Ok(Json(body))
     }
 
     fn content_type_handler(ContentType(content_type): ContentType) -> String {
@@ -154,9 +158,9 @@ fn test_with_client() -> anyhow::Result<()> {
 
 #[test]
 fn test_with_client_2_param_handlers() -> anyhow::Result<()> {
-    fn handler(PathParam(user): PathParam<String>, Json(mut body): Json<OwnBody>) -> OwnBody {
+    fn handler(PathParam(user): PathParam<String>, Json(mut body): Json<OwnBody>) -> Json<OwnBody> {
         body.val = user;
-        body
+        Json(body)
     }
 
     let body = r#"{"val":"string value","val2":123,"val3":true}"#;
@@ -181,10 +185,10 @@ fn test_with_client_3_param_handlers() -> anyhow::Result<()> {
         PathParam(user): PathParam<String>,
         PathParam(id): PathParam<i32>,
         Json(mut body): Json<OwnBody>,
-    ) -> OwnBody {
+    ) -> Json<OwnBody> {
         body.val = user;
         body.val2 = id;
-        body
+        Json(body)
     }
 
     let body = r#"{"val":"string value","val2":123,"val3":true}"#;
@@ -283,10 +287,10 @@ fn test_state_with_extractors() -> anyhow::Result<()> {
         state: State<Config>,
         Query(params): Query<QueryParams>,
         Json(mut body): Json<OwnBody>,
-    ) -> anyhow::Result<String> {
+    ) -> Json<OwnBody> {
         body.val = state.0.db_host;
         body.val2 = params.age;
-        Ok(serde_json::to_string(&body)?)
+        Json(body)
     }
 
     let cfg = Config {
@@ -311,6 +315,46 @@ fn test_state_with_extractors() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_substate() -> anyhow::Result<()> {
+    #[derive(Clone)]
+    struct Database(&'static str);
+
+    #[derive(Clone, FromRef)]
+    struct AppState {
+        db: Database,
+        port: u16,
+        #[from_ref(skip)]
+        other_port: u16,
+    }
+
+    fn db(State(Database(url)): State<Database>) -> &'static str {
+        url
+    }
+
+    fn ports(State(port): State<u16>, State(state): State<AppState>) -> String {
+        format!("{} {}", port, state.other_port)
+    }
+
+    let app = Router::with_state(AppState {
+        db: Database("postgres://"),
+        port: 8080,
+        other_port: 8081,
+    })
+    .get("/db", db)
+    .get("/ports", ports);
+
+    TestCaseBuilder::new("/db", Method::GET, app.clone())
+        .name("field of state")
+        .result("postgres://")
+        .run()?;
+    TestCaseBuilder::new("/ports", Method::GET, app)
+        .name("field and whole state")
+        .result("8080 8081")
+        .run()?;
+    Ok(())
+}
+
 #[test]
 fn test_route_group() -> anyhow::Result<()> {
     let v1 = RouteGroup::new("/v1")
@@ -341,3 +385,1190 @@ fn test_route_group() -> anyhow::Result<()> {
         .run()?;
     Ok(())
 }
+
+#[test]
+fn test_async_handlers() -> anyhow::Result<()> {
+    async fn empty() {}
+
+    async fn echo(body: String) -> String {
+        body
+    }
+
+    async fn handler(
+        PathParam(user): PathParam<String>,
+        Json(mut body): Json<OwnBody>,
+    ) -> Json<OwnBody> {
+        body.val = user;
+        Json(body)
+    }
+
+    TestCaseBuilder::new("/", Method::GET, Router::default().get("/", empty))
+        .name("async empty")
+        .run()?;
+
+    TestCaseBuilder::new("/echo", Method::POST, Router::default().post("/echo", echo))
+        .name("async echo")
+        .body(Body::from("hello"))
+        .result("hello")
+        .run()?;
+
+    TestCaseBuilder::new(
+        "/body/username",
+        Method::POST,
+        Router::default().post("/body/<user>", handler),
+    )
+    .name("async handler with path param and body")
+    .body(Body::from(
+        r#"{"val":"string value","val2":123,"val3":true}"#,
+    ))
+    .result(r#"{"val":"username","val2":123,"val3":true}"#)
+    .run()?;
+
+    Ok(())
+}
+
+/// Middleware that records when its hooks are run.
+#[derive(Clone)]
+struct Recorder {
+    name: &'static str,
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl Middleware for Recorder {
+    fn on_request(&self, _req: &mut Request<Body>) -> anyhow::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{}::on_request", self.name));
+        Ok(())
+    }
+
+    fn on_response(&self, _res: &mut Response) -> anyhow::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{}::on_response", self.name));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_global_middlewares() {
+    let calls = Arc::new(Mutex::new(vec![]));
+    let recorder = |name| Recorder {
+        name,
+        calls: calls.clone(),
+    };
+
+    let group = RouteGroup::new("/v1")
+        .middleware(recorder("group"))
+        .get("/user", (|| "v1").into_service());
+    let app = Router::default()
+        .middleware(recorder("first"))
+        .middleware(recorder("second"))
+        .groups(vec![group]);
+
+    let request = |uri| Request::get(uri).body(Body::empty()).unwrap();
+
+    let response = app.call(request("/v1/user"));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            "first::on_request",
+            "second::on_request",
+            "group::on_request",
+            "group::on_response",
+            "second::on_response",
+            "first::on_response",
+        ]
+    );
+
+    // Global middlewares run also when no route matches.
+    calls.lock().unwrap().clear();
+    app.call(request("/missing"));
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            "first::on_request",
+            "second::on_request",
+            "second::on_response",
+            "first::on_response",
+        ]
+    );
+}
+
+/// Answers with cached body without calling the handler.
+struct Cache;
+
+impl AroundMiddleware for Cache {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> Response {
+        if req.uri().path() == "/v1/cached" {
+            return Response::new(Body::from("from cache"));
+        }
+        next.run(req)
+    }
+}
+
+#[test]
+fn test_around_middlewares() {
+    let auth = from_fn(|req: Request<Body>, next: Next| {
+        if req.headers().contains_key(hyper::header::AUTHORIZATION) {
+            return next.run(req);
+        }
+
+        hyper::Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap()
+    });
+
+    let group = RouteGroup::new("/v1")
+        .middleware(Cache)
+        .get("/user", (|| "user").into_service())
+        .get("/cached", (|| -> &str { unreachable!() }).into_service());
+    let app = Router::default().middleware(auth).groups(vec![group]);
+
+    let request = |uri, authorized| {
+        let mut builder = Request::get(uri);
+        if authorized {
+            builder = builder.header(hyper::header::AUTHORIZATION, "token");
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    let response = app.call(request("/v1/user", false));
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.call(request("/v1/user", true));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        core::response::body_to_bytes(response.into_body()).unwrap(),
+        "user"
+    );
+
+    let response = app.call(request("/v1/cached", true));
+    assert_eq!(
+        core::response::body_to_bytes(response.into_body()).unwrap(),
+        "from cache"
+    );
+}
+
+#[test]
+fn test_not_found_and_method_not_allowed() {
+    fn handler() {}
+
+    let app = Router::default()
+        .get("/user/<id>", handler)
+        .post("/user/<id>", handler);
+    let request = |method, uri| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.call(request(Method::GET, "/missing"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app.call(request(Method::DELETE, "/user/1"));
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        response.headers()[hyper::header::ALLOW],
+        "GET, HEAD, OPTIONS, POST"
+    );
+
+    fn fallback(req: Request<Body>) -> Response {
+        let allowed = req.extensions().get::<AllowedMethods>().unwrap();
+        let status = match allowed.0.is_empty() {
+            true => StatusCode::NOT_FOUND,
+            false => StatusCode::METHOD_NOT_ALLOWED,
+        };
+        hyper::Response::builder()
+            .status(status)
+            .body(Body::from("fallback"))
+            .unwrap()
+    }
+
+    let app = app.fallback(fallback);
+
+    let response = app.call(request(Method::GET, "/missing"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        core::response::body_to_bytes(response.into_body()).unwrap(),
+        "fallback"
+    );
+
+    let response = app.call(request(Method::DELETE, "/user/1"));
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        response.headers()[hyper::header::ALLOW],
+        "GET, HEAD, OPTIONS, POST"
+    );
+}
+
+#[test]
+fn test_methods() -> anyhow::Result<()> {
+    fn name(request: Request<Body>) -> String {
+        request.method().to_string()
+    }
+
+    fn head() -> hyper::Response<Body> {
+        hyper::Response::builder()
+            .header("x-head", "explicit")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    let group = RouteGroup::new("/group").put("/item", name).on(
+        MethodFilter::PATCH | MethodFilter::DELETE,
+        "/item",
+        name,
+    );
+    let app = Router::default()
+        .get("/item", name)
+        .post("/item", name)
+        .trace("/item", name)
+        .any("/any", name)
+        .get("/head", name)
+        .head("/head", head)
+        .groups(vec![group]);
+
+    let request = |method: Method, uri: &str| TestCaseBuilder::new(uri, method, app.clone());
+
+    for method in [Method::GET, Method::POST, Method::TRACE] {
+        request(method.clone(), "/item")
+            .result(method.as_str())
+            .run()?;
+    }
+    for method in [Method::PUT, Method::PATCH, Method::DELETE] {
+        request(method.clone(), "/group/item")
+            .result(method.as_str())
+            .run()?;
+    }
+    for method in [Method::DELETE, Method::HEAD, Method::OPTIONS] {
+        request(method.clone(), "/any")
+            .result(method.as_str())
+            .run()?;
+    }
+
+    // HEAD is answered by GET route, handler sees the HEAD method.
+    // Server drops the body when the response is written.
+    request(Method::HEAD, "/item")
+        .status(StatusCode::OK)
+        .result("HEAD")
+        .run()?;
+
+    request(Method::HEAD, "/head")
+        .response_header("x-head", "explicit")
+        .run()?;
+
+    request(Method::OPTIONS, "/item")
+        .status(StatusCode::NO_CONTENT)
+        .response_header(hyper::header::ALLOW, "GET, HEAD, OPTIONS, POST, TRACE")
+        .run()?;
+    assert_eq!(
+        request(Method::OPTIONS, "/missing").send()?.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        request(Method::HEAD, "/group/item").send()?.status,
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejections() -> anyhow::Result<()> {
+    fn json(Json(body): Json<OwnBody>) -> String {
+        body.val
+    }
+
+    fn id(PathParam(id): PathParam<u32>) -> String {
+        id.to_string()
+    }
+
+    fn host(Host(host): Host) -> String {
+        host
+    }
+
+    fn optional_host(host: Option<Host>) -> String {
+        host.map(|Host(h)| h).unwrap_or_else(|| "no host".into())
+    }
+
+    fn checked_json(body: Result<Json<OwnBody>, Rejection>) -> String {
+        body.map(|Json(body)| body.val)
+            .unwrap_or_else(|rejection| rejection.status().to_string())
+    }
+
+    let app = Router::default()
+        .post("/json", json)
+        .post("/checked-json", checked_json)
+        .get("/id/<id>", id)
+        .get("/host", host)
+        .get("/optional-host", optional_host);
+
+    let post = |uri: &str, content_type: &str, body: &'static str| {
+        TestCaseBuilder::new(uri, Method::POST, app.clone())
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+    };
+    let get = |uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    let valid = r#"{"val":"value","val2":1,"val3":true}"#;
+    let json_type = "application/json; charset=utf-8";
+    post("/json", json_type, valid)
+        .status(StatusCode::OK)
+        .result("value")
+        .run()?;
+    assert_eq!(
+        post("/json", json_type, "{").send()?.status,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        post("/json", json_type, r#"{"val":1}"#).send()?.status,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        post("/json", "text/plain", valid).send()?.status,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    post("/checked-json", json_type, "{")
+        .status(StatusCode::OK)
+        .result("400 Bad Request")
+        .run()?;
+
+    assert_eq!(get("/id/abc").send()?.status, StatusCode::BAD_REQUEST);
+    get("/host")
+        .status(StatusCode::BAD_REQUEST)
+        .result("missing header host")
+        .run()?;
+    get("/optional-host")
+        .status(StatusCode::OK)
+        .result("no host")
+        .run()?;
+    Ok(())
+}
+
+#[test]
+fn test_body_extractors() -> anyhow::Result<()> {
+    #[derive(Deserialize)]
+    struct Login {
+        user: String,
+        remember: bool,
+    }
+
+    fn form(Form(login): Form<Login>) -> String {
+        format!("{} {}", login.user, login.remember)
+    }
+
+    fn bytes(body: hyper::body::Bytes) -> String {
+        body.len().to_string()
+    }
+
+    async fn upload(multipart: Multipart) -> Result<String, Rejection> {
+        let mut multipart = multipart.field_limit(8).limit_for("file", 16);
+
+        let mut fields = vec![];
+        while let Some(field) = multipart.next_field().await? {
+            let name = field.name().unwrap_or_default().to_string();
+            let file_name = field.file_name().map(str::to_string);
+            let text = field.text().await?;
+            fields.push(match file_name {
+                Some(file_name) => format!("{}({})={}", name, file_name, text),
+                None => format!("{}={}", name, text),
+            });
+        }
+        std::result::Result::Ok(fields.join(","))
+    }
+
+    let app = Router::default()
+        .post("/form", form)
+        .post("/text", |body: String| body)
+        .post("/bytes", bytes)
+        .post("/upload", upload);
+
+    let post = |uri: &str, content_type: &str, body: &'static str| {
+        TestCaseBuilder::new(uri, Method::POST, app.clone())
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+    };
+
+    let form_type = "application/x-www-form-urlencoded";
+    post("/form", form_type, "user=john+doe&remember=true")
+        .status(StatusCode::OK)
+        .result("john doe true")
+        .run()?;
+    assert_eq!(
+        post("/form", form_type, "user=john").send()?.status,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        post("/form", "application/json", "{}").send()?.status,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+
+    post("/text", "text/plain; charset=utf-8", "hello")
+        .status(StatusCode::OK)
+        .result("hello")
+        .run()?;
+    assert_eq!(
+        post("/text", "application/octet-stream", "hello")
+            .send()?
+            .status,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+
+    post("/bytes", "application/octet-stream", "\x00\x01\x02")
+        .status(StatusCode::OK)
+        .result("3")
+        .run()?;
+
+    let multipart_type = "multipart/form-data; boundary=X-BOUNDARY";
+    let body = "--X-BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        holiday\r\n\
+        --X-BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        sunny beach\r\n\
+        --X-BOUNDARY--\r\n";
+    post("/upload", multipart_type, body)
+        .status(StatusCode::OK)
+        .result("title=holiday,file(a.txt)=sunny beach")
+        .run()?;
+
+    let too_large = "--X-BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        long holiday\r\n\
+        --X-BOUNDARY--\r\n";
+    assert_eq!(
+        post("/upload", multipart_type, too_large).send()?.status,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        post("/upload", "multipart/form-data", body).send()?.status,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        post("/upload", form_type, body).send()?.status,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    Ok(())
+}
+
+#[test]
+fn test_body_limit() -> anyhow::Result<()> {
+    fn echo(body: String) -> String {
+        body
+    }
+
+    async fn count(mut body: BodyStream) -> Result<String, Rejection> {
+        let mut size = 0;
+        while let Some(chunk) = body.chunk().await? {
+            size += chunk.len();
+        }
+        std::result::Result::Ok(size.to_string())
+    }
+
+    let uploads = Router::default()
+        .body_limit(16)
+        .post("/upload", count)
+        .post("/upload/echo", echo);
+    let unlimited = RouteGroup::new("/unlimited")
+        .middleware(BodyLimit::unlimited())
+        .post("/echo", echo);
+
+    let app = Router::default()
+        .body_limit(4)
+        .post("/echo", echo)
+        .post("/count", count)
+        .merge(uploads)
+        .groups(vec![unlimited]);
+
+    let post = |uri: &str, body: String, content_length: bool| {
+        TestCaseBuilder::new(uri, Method::POST, app.clone())
+            .maybe_header(
+                hyper::header::CONTENT_LENGTH,
+                content_length.then_some(body.len()),
+            )
+            .body(Body::from(body))
+    };
+
+    post("/echo", "abcd".into(), true)
+        .status(StatusCode::OK)
+        .result("abcd")
+        .run()?;
+    post("/echo", "abcde".into(), true)
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .result("body is larger than 4 bytes")
+        .run()?;
+    assert_eq!(
+        post("/echo", "abcde".into(), false).send()?.status,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        post("/count", "abcde".into(), false).send()?.status,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+
+    post("/upload", "a".repeat(16), false)
+        .status(StatusCode::OK)
+        .result("16")
+        .run()?;
+    assert_eq!(
+        post("/upload/echo", "a".repeat(17), true).send()?.status,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+
+    let large = "a".repeat(DEFAULT_BODY_LIMIT + 1);
+    let response = post("/unlimited/echo", large.clone(), true).send()?;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body.len(), large.len());
+
+    let default = Router::default().post("/echo", echo);
+    let response = TestCaseBuilder::new("/echo", Method::POST, default)
+        .body(Body::from(large))
+        .send()?;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Server doesn't cap bodies on its own, router's limit is the only one.
+    let size = 11 * 1024 * 1024;
+    let uploads = Router::default()
+        .body_limit(100 * 1024 * 1024)
+        .post("/count", count);
+    TestCaseBuilder::new("/count", Method::POST, uploads)
+        .body(Body::from("a".repeat(size)))
+        .status(StatusCode::OK)
+        .result(&size.to_string())
+        .run()?;
+    Ok(())
+}
+
+#[test]
+fn test_typed_headers() -> anyhow::Result<()> {
+    fn whoami(TypedHeader(auth): TypedHeader<Authorization>) -> String {
+        match auth {
+            Authorization::Bearer(token) => format!("token {}", token),
+            Authorization::Basic { username, .. } => format!("user {}", username),
+        }
+    }
+
+    fn etag(if_none_match: Option<TypedHeader<IfNoneMatch>>) -> String {
+        match if_none_match {
+            Some(TypedHeader(tags)) if tags.matches("\"v1\"") => "not modified".into(),
+            _ => "fresh".into(),
+        }
+    }
+
+    fn user_agent(TypedHeader(UserAgent(agent)): TypedHeader<UserAgent>) -> String {
+        agent
+    }
+
+    fn range() -> TypedHeader<Range> {
+        TypedHeader(Range(vec![ByteRange::FromTo(0, 9), ByteRange::Last(5)]))
+    }
+
+    let app = Router::default()
+        .get("/whoami", whoami)
+        .get("/etag", etag)
+        .get("/agent", user_agent)
+        .get("/range", range);
+
+    let get = |uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    get("/whoami")
+        .header("authorization", "Bearer abc")
+        .status(StatusCode::OK)
+        .result("token abc")
+        .run()?;
+    get("/whoami")
+        .header("authorization", "Basic am9objpwYXNz")
+        .status(StatusCode::OK)
+        .result("user john")
+        .run()?;
+    get("/whoami")
+        .status(StatusCode::BAD_REQUEST)
+        .result("missing header authorization")
+        .run()?;
+    assert_eq!(
+        get("/whoami")
+            .header("authorization", "Digest abc")
+            .send()?
+            .status,
+        StatusCode::BAD_REQUEST
+    );
+
+    get("/etag")
+        .header("if-none-match", "W/\"v1\", \"v2\"")
+        .result("not modified")
+        .run()?;
+    get("/etag")
+        .header("if-none-match", "\"v2\"")
+        .result("fresh")
+        .run()?;
+    get("/etag").result("fresh").run()?;
+
+    get("/agent")
+        .header("user-agent", "curl/7.85.0")
+        .result("curl/7.85.0")
+        .run()?;
+
+    get("/range")
+        .response_header("range", "bytes=0-9, -5")
+        .run()?;
+    Ok(())
+}
+
+#[test]
+fn test_cookies() -> anyhow::Result<()> {
+    #[derive(Clone, FromRef)]
+    struct AppState {
+        key: Key,
+    }
+
+    fn visit(jar: CookieJar) -> (CookieJar, String) {
+        let visits: u32 = jar
+            .get("visits")
+            .and_then(|cookie| cookie.value().parse().ok())
+            .unwrap_or_default();
+        let cookie = Cookie::new("visits", (visits + 1).to_string())
+            .path("/")
+            .same_site(SameSite::Strict);
+        (jar.insert(cookie), visits.to_string())
+    }
+
+    fn forget(jar: CookieJar) -> CookieJar {
+        jar.remove(Cookie::named("visits").path("/"))
+    }
+
+    fn login(signed: SignedCookieJar) -> SignedCookieJar {
+        signed.insert(Cookie::new("user", "john").http_only(true))
+    }
+
+    fn user(signed: SignedCookieJar) -> String {
+        signed
+            .get("user")
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or_else(|| "anonymous".into())
+    }
+
+    fn set_secret(private: PrivateCookieJar) -> anyhow::Result<PrivateCookieJar> {
+        private.insert(Cookie::new("secret", "42"))
+    }
+
+    fn secret(private: PrivateCookieJar) -> String {
+        private
+            .get("secret")
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or_default()
+    }
+
+    let app = Router::with_state(AppState {
+        key: Key::generate(),
+    })
+    .get("/visit", visit)
+    .get("/forget", forget)
+    .get("/login", login)
+    .get("/user", user)
+    .get("/secret/set", set_secret)
+    .get("/secret", secret);
+
+    let get = |uri: &str, cookie: Option<&str>| {
+        TestCaseBuilder::new(uri, Method::GET, app.clone()).maybe_header("cookie", cookie)
+    };
+
+    get("/visit", None)
+        .response_header("set-cookie", "visits=1; Path=/; SameSite=Strict")
+        .result("0")
+        .run()?;
+    get("/visit", Some("a=b; visits=5")).result("5").run()?;
+    get("/forget", Some("visits=5"))
+        .response_header(
+            "set-cookie",
+            "visits=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        )
+        .run()?;
+
+    let response = get("/login", None).send()?;
+    assert!(response
+        .header("set-cookie")
+        .unwrap()
+        .ends_with("john; HttpOnly"));
+    let signed = response.cookie_pair().unwrap();
+    get("/user", Some(&signed)).result("john").run()?;
+    get("/user", Some(&signed.replace("john", "jane")))
+        .result("anonymous")
+        .run()?;
+    get("/user", Some("user=john")).result("anonymous").run()?;
+
+    let private = get("/secret/set", None).send()?.cookie_pair().unwrap();
+    assert!(!private.contains("42"));
+    get("/secret", Some(&private)).result("42").run()?;
+    get("/secret", Some(&signed)).result("").run()?;
+    Ok(())
+}
+
+#[test]
+fn test_sessions() -> anyhow::Result<()> {
+    fn login(session: Session) -> anyhow::Result<()> {
+        session.rotate_id();
+        session.insert("user", "john")
+    }
+
+    fn user(session: Session) -> String {
+        session
+            .get("user")
+            .unwrap_or_else(|| "anonymous".to_string())
+    }
+
+    fn logout(session: Session) {
+        session.destroy();
+    }
+
+    let store = MemoryStore::default();
+    let app = Router::default()
+        .middleware(SessionMiddleware::new(store.clone()).cookie_name("sid"))
+        .get("/login", login)
+        .get("/user", user)
+        .get("/logout", logout);
+
+    let get = |app: &Router<()>, uri: &str, cookie: Option<&str>| {
+        TestCaseBuilder::new(uri, Method::GET, app.clone()).maybe_header("cookie", cookie)
+    };
+
+    let response = get(&app, "/user", None).send()?;
+    assert_eq!(response.header("set-cookie"), None);
+    assert_eq!(response.text(), "anonymous");
+
+    let response = get(&app, "/login", None).send()?;
+    let set_cookie = response.header("set-cookie").unwrap();
+    assert!(set_cookie.starts_with("sid="));
+    assert!(set_cookie.ends_with("; Path=/; Max-Age=86400; HttpOnly; SameSite=Lax"));
+    let first = response.cookie_pair().unwrap();
+    let response = get(&app, "/user", Some(&first)).send()?;
+    assert_eq!(response.header("set-cookie"), None);
+    assert_eq!(response.text(), "john");
+
+    // Login again rotates ID, the old one is no longer valid.
+    let response = get(&app, "/login", Some(&first)).send()?;
+    let second = response.cookie_pair().unwrap();
+    assert_ne!(first, second);
+    get(&app, "/user", Some(&first)).result("anonymous").run()?;
+    get(&app, "/user", Some(&second)).result("john").run()?;
+
+    let set_cookie = get(&app, "/logout", Some(&second))
+        .send()?
+        .header("set-cookie");
+    assert!(set_cookie.unwrap().starts_with("sid=; Path=/; Max-Age=0"));
+    get(&app, "/user", Some(&second))
+        .result("anonymous")
+        .run()?;
+    get(&app, "/user", Some("sid=../../etc/passwd"))
+        .result("anonymous")
+        .run()?;
+
+    let expiring = Router::default()
+        .middleware(SessionMiddleware::new(store).ttl(Duration::ZERO))
+        .get("/login", login)
+        .get("/user", user);
+    let cookie = get(&expiring, "/login", None)
+        .send()?
+        .cookie_pair()
+        .unwrap();
+    get(&expiring, "/user", Some(&cookie))
+        .result("anonymous")
+        .run()?;
+
+    let without_middleware = Router::default().get("/user", user);
+    assert_eq!(
+        get(&without_middleware, "/user", None).send()?.status,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_and_negotiate() -> anyhow::Result<()> {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u8,
+    }
+
+    fn json() -> Json<User> {
+        Json(User {
+            name: "john".into(),
+            age: 30,
+        })
+    }
+
+    fn negotiate(negotiate: Negotiate) -> Negotiate<User> {
+        negotiate.respond(User {
+            name: "john".into(),
+            age: 30,
+        })
+    }
+
+    let app = Router::default()
+        .get("/json", json)
+        .get("/negotiate", negotiate);
+
+    let get = |uri: &str, accept: Option<&str>| {
+        TestCaseBuilder::new(uri, Method::GET, app.clone()).maybe_header("accept", accept)
+    };
+
+    let json_type = "application/json";
+    let json_body = r#"{"name":"john","age":30}"#;
+    get("/json", None)
+        .status(StatusCode::OK)
+        .response_header("content-type", json_type)
+        .result(json_body)
+        .run()?;
+
+    get("/negotiate", None)
+        .status(StatusCode::OK)
+        .response_header("content-type", json_type)
+        .result(json_body)
+        .run()?;
+    get("/negotiate", Some("*/*"))
+        .status(StatusCode::OK)
+        .response_header("content-type", json_type)
+        .result(json_body)
+        .run()?;
+    get(
+        "/negotiate",
+        Some("application/json;q=0.5, application/x-www-form-urlencoded"),
+    )
+    .status(StatusCode::OK)
+    .response_header("content-type", "application/x-www-form-urlencoded")
+    .result("name=john&age=30")
+    .run()?;
+
+    let response = get("/negotiate", Some("application/x-bincode")).send()?;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.header("content-type").unwrap(),
+        "application/x-bincode"
+    );
+    assert_eq!(
+        bincode::deserialize::<User>(&response.body).unwrap(),
+        User {
+            name: "john".into(),
+            age: 30
+        }
+    );
+
+    assert_eq!(
+        get("/negotiate", Some("text/html")).send()?.status,
+        StatusCode::NOT_ACCEPTABLE
+    );
+    Ok(())
+}
+
+#[test]
+fn test_error_responses() -> anyhow::Result<()> {
+    fn not_found() -> Result<String, HttpError> {
+        Err(HttpError::not_found("no such user").with_details(["id"]))
+    }
+
+    fn forbidden() -> anyhow::Result<String> {
+        Err(HttpError::forbidden("not yours"))?
+    }
+
+    fn failing() -> anyhow::Result<String> {
+        anyhow::bail!("database is down")
+    }
+
+    let app = Router::default()
+        .get("/not-found", not_found)
+        .get("/forbidden", forbidden)
+        .get("/failing", failing);
+
+    let get = |app: &Router<()>, uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    get(&app, "/not-found")
+        .status(StatusCode::NOT_FOUND)
+        .result(r#"{"error":"no such user","details":["id"]}"#)
+        .run()?;
+    get(&app, "/forbidden")
+        .status(StatusCode::FORBIDDEN)
+        .result(r#"{"error":"not yours"}"#)
+        .run()?;
+    get(&app, "/failing")
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .result("database is down")
+        .run()?;
+
+    let app = app.error_handler(|_err: anyhow::Error| {
+        HttpError::new(StatusCode::SERVICE_UNAVAILABLE, "try again later")
+    });
+    get(&app, "/failing")
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .result(r#"{"error":"try again later"}"#)
+        .run()?;
+    // Errors rendered by the application are not passed to the error handler.
+    assert_eq!(
+        get(&app, "/forbidden").send()?.status,
+        StatusCode::FORBIDDEN
+    );
+    Ok(())
+}
+
+#[test]
+fn test_static_routes_take_precedence() -> anyhow::Result<()> {
+    fn user(PathParam(id): PathParam<String>) -> String {
+        format!("user {}", id)
+    }
+
+    fn me() -> &'static str {
+        "me"
+    }
+
+    let app = Router::default()
+        .get("/users/<id>", user)
+        .get("/users/me", me);
+
+    TestCaseBuilder::new("/users/me", Method::GET, app.clone())
+        .name("static route registered later")
+        .result("me")
+        .run()?;
+    TestCaseBuilder::new("/users/10", Method::GET, app)
+        .name("param route")
+        .result("user 10")
+        .run()?;
+    Ok(())
+}
+
+#[test]
+fn test_path_params() -> anyhow::Result<()> {
+    #[derive(Deserialize)]
+    struct Comment {
+        id: u32,
+        post: u32,
+    }
+
+    fn post(Path((user, post)): Path<(String, u32)>) -> String {
+        format!("{} {}", user, post)
+    }
+
+    fn comment(Path(comment): Path<Comment>) -> String {
+        format!("{} {}", comment.post, comment.id)
+    }
+
+    fn raw(params: RawPathParams) -> String {
+        params
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn positional(PathParam(a): PathParam<String>, PathParam(b): PathParam<u32>) -> String {
+        format!("{} {}", a, b)
+    }
+
+    let app = Router::default()
+        .get("/users/<user>/posts/<post>", post)
+        .get("/posts/<post>/comments/<id>", comment)
+        .get("/raw/<first>/<second>", raw)
+        .get("/positional/<a>/<b>", positional);
+
+    let get = |uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    get("/users/John%20Doe/posts/3")
+        .status(StatusCode::OK)
+        .result("John Doe 3")
+        .run()?;
+    get("/posts/3/comments/12")
+        .status(StatusCode::OK)
+        .result("3 12")
+        .run()?;
+    get("/raw/a%2Fb/%C5%BC")
+        .status(StatusCode::OK)
+        .result("first=a/b,second=ż")
+        .run()?;
+    get("/positional/x%3Fy/5")
+        .status(StatusCode::OK)
+        .result("x?y 5")
+        .run()?;
+
+    assert_eq!(
+        get("/posts/3/comments/abc").send()?.status,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(get("/raw/%FF/x").send()?.status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[test]
+fn test_catch_all_optional_and_constrained_params() -> anyhow::Result<()> {
+    fn file(Path(path): Path<String>) -> String {
+        path
+    }
+
+    fn item(Path(id): Path<u32>) -> String {
+        format!("item {}", id)
+    }
+
+    fn slug(Path(slug): Path<String>) -> String {
+        format!("slug {}", slug)
+    }
+
+    fn posts(Path(page): Path<Option<u32>>) -> String {
+        format!("page {}", page.unwrap_or(1))
+    }
+
+    let app = Router::default()
+        .get("/static/<path..>", file)
+        .get("/items/<id:u32>", item)
+        .get("/items/<slug:[a-z-]+>", slug)
+        .get("/posts/<page?:u32>", posts);
+
+    let get = |uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    get("/static/css/main%20file.css")
+        .status(StatusCode::OK)
+        .result("css/main file.css")
+        .run()?;
+    get("/items/42")
+        .status(StatusCode::OK)
+        .result("item 42")
+        .run()?;
+    get("/items/new-item")
+        .status(StatusCode::OK)
+        .result("slug new-item")
+        .run()?;
+    get("/posts")
+        .status(StatusCode::OK)
+        .result("page 1")
+        .run()?;
+    get("/posts/3")
+        .status(StatusCode::OK)
+        .result("page 3")
+        .run()?;
+
+    assert_eq!(get("/static/").send()?.status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/items/New_Item").send()?.status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/posts/last").send()?.status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "has to be the last segment")]
+fn test_invalid_catch_all_route() {
+    fn handler() {}
+
+    let _ = Router::default().get("/static/<path..>/raw", handler);
+}
+
+#[test]
+fn test_nest_and_merge() -> anyhow::Result<()> {
+    #[derive(Clone)]
+    struct AppState {
+        name: &'static str,
+    }
+
+    fn name(State(state): State<AppState>) -> &'static str {
+        state.name
+    }
+
+    fn count(State(count): State<u32>) -> String {
+        count.to_string()
+    }
+
+    fn user(Path((org, id)): Path<(String, u32)>) -> String {
+        format!("{} {}", org, id)
+    }
+
+    fn tag(request: Request<Body>) -> &'static str {
+        match request.headers().contains_key("x-nested") {
+            true => "nested",
+            false => "plain",
+        }
+    }
+
+    let nested_only = from_fn(|mut req: Request<Body>, next: Next<'_>| {
+        req.headers_mut()
+            .insert("x-nested", hyper::header::HeaderValue::from_static("1"));
+        next.run(req)
+    });
+
+    let users = Router::inherit_state()
+        .get("/", name)
+        .get("/<id>", user)
+        .middleware(nested_only);
+    let deep = Router::<AppState>::inherit_state()
+        .nest("/deep", Router::inherit_state().get("/name", name));
+    let counter = Router::with_state(7u32).get("/count", count);
+
+    let app = Router::with_state(AppState { name: "app" })
+        .get("/tag", tag)
+        .nest("/orgs/<org>/users", users.get("/me/tag", tag))
+        .nest("/inherited", deep)
+        .merge(counter);
+
+    let get = |uri: &str| TestCaseBuilder::new(uri, Method::GET, app.clone());
+
+    get("/orgs/acme/users")
+        .status(StatusCode::OK)
+        .result("app")
+        .run()?;
+    get("/orgs/acme/users/12")
+        .status(StatusCode::OK)
+        .result("acme 12")
+        .run()?;
+    get("/inherited/deep/name")
+        .status(StatusCode::OK)
+        .result("app")
+        .run()?;
+    get("/count").status(StatusCode::OK).result("7").run()?;
+
+    // Nested router's middlewares run only for its routes.
+    get("/orgs/acme/users/me/tag")
+        .status(StatusCode::OK)
+        .result("nested")
+        .run()?;
+    get("/tag").status(StatusCode::OK).result("plain").run()?;
+
+    // Router that never got its state fails instead of panicking.
+    let orphan = Router::<AppState>::inherit_state().get("/", name);
+    assert_eq!(
+        TestCaseBuilder::new("/", Method::GET, orphan)
+            .send()?
+            .status,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "conflicts with already registered")]
+fn test_conflicting_nested_routes() {
+    fn handler() {}
+
+    let _ = Router::default()
+        .get("/api/users", handler)
+        .nest("/api", Router::default().get("/users", handler));
+}
+
+#[test]
+#[should_panic(expected = "has no state")]
+fn test_nested_router_without_state() {
+    fn handler(State(count): State<u32>) -> String {
+        count.to_string()
+    }
+
+    let _ = Router::default().nest("/api", Router::inherit_state().get("/", handler));
+}
+
+#[test]
+#[should_panic(expected = "conflicts with already registered")]
+fn test_conflicting_routes() {
+    fn handler() {}
+
+    let _ = Router::default()
+        .get("/users/<id>", handler)
+        .get("/users/<id>", handler);
+}
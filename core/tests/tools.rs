@@ -3,7 +3,7 @@ use core::{
     response::{body_to_bytes, Response},
     server::Server,
 };
-use hyper::{Body, Method, Request};
+use hyper::{Body, HeaderMap, Method, Request, StatusCode};
 use std::collections::HashMap;
 
 struct Client<V> {
@@ -36,6 +36,47 @@ pub struct TestCaseBuilder<V> {
     body: Option<Body>,
     headers: Option<HashMap<String, String>>,
     result: Option<Vec<u8>>,
+
+    /// Expected status code, not checked when missing.
+    status: Option<StatusCode>,
+
+    /// Expected response's headers.
+    response_headers: Vec<(String, String)>,
+}
+
+/// Response returned by `TestCaseBuilder::send`, with the body already read.
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// Returns body as UTF-8 text.
+    pub fn text(&self) -> String {
+        String::from_utf8(self.body.clone()).expect("body is valid UTF-8")
+    }
+
+    /// Returns first value of the header.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.header_values(name).into_iter().next()
+    }
+
+    /// Returns `name=value` part of the first `Set-Cookie` header, ready to be
+    /// sent back in `Cookie` header.
+    pub fn cookie_pair(&self) -> Option<String> {
+        let set_cookie = self.header("set-cookie")?;
+        set_cookie.split(';').next().map(str::to_string)
+    }
+
+    /// Returns all values of the header.
+    pub fn header_values(&self, name: &str) -> Vec<String> {
+        self.headers
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect()
+    }
 }
 
 impl<V> TestCaseBuilder<V>
@@ -55,6 +96,8 @@ where
             headers: None,
             body: None,
             result: None,
+            status: None,
+            response_headers: vec![],
         }
     }
 
@@ -73,6 +116,22 @@ where
         self
     }
 
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Expects response to have the header with the value.
+    pub fn response_header<K, L>(mut self, key: K, value: L) -> Self
+    where
+        K: ToString,
+        L: ToString,
+    {
+        self.response_headers
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
     pub fn header<K, L>(mut self, key: K, value: L) -> Self
     where
         K: ToString,
@@ -84,7 +143,60 @@ where
         self
     }
 
-    pub fn run(self) -> anyhow::Result<()> {
+    /// Sets the header when value is given.
+    pub fn maybe_header<K, L>(self, key: K, value: Option<L>) -> Self
+    where
+        K: ToString,
+        L: ToString,
+    {
+        match value {
+            Some(value) => self.header(key, value),
+            None => self,
+        }
+    }
+
+    /// Sends request and checks the response. Body is expected to be empty
+    /// unless `result` was set.
+    pub fn run(mut self) -> anyhow::Result<()> {
+        let name = self.name.take().unwrap_or_default();
+        let result = self.result.take().unwrap_or_default();
+        let status = self.status.take();
+        let response_headers = std::mem::take(&mut self.response_headers);
+
+        let response = self.send()?;
+
+        if let Some(status) = status {
+            assert_eq!(
+                response.status,
+                status,
+                "test case {}, body: {}",
+                name,
+                String::from_utf8_lossy(&response.body)
+            );
+        }
+        for (key, value) in response_headers {
+            assert_eq!(
+                response.header_values(&key),
+                vec![value],
+                "test case {}, header {}",
+                name,
+                key
+            );
+        }
+        assert_eq!(
+            response.body,
+            result,
+            "test case {}, left: {}, right: {}",
+            name,
+            String::from_utf8_lossy(&response.body),
+            String::from_utf8_lossy(&result)
+        );
+
+        Ok(())
+    }
+
+    /// Sends request and returns the response without checking it.
+    pub fn send(self) -> anyhow::Result<TestResponse> {
         let mut builder = Request::builder().uri(self.url).method(self.method.clone());
 
         for (key, value) in self.headers.unwrap_or_default().into_iter() {
@@ -96,18 +208,12 @@ where
         let client = Client::new(self.service);
 
         let res: Response = client.send(req)?;
-        let body_bytes: Vec<u8> = body_to_bytes(res.into_body())?.into();
+        let (parts, body) = res.into_parts();
 
-        let res = self.result.unwrap_or_default().to_vec();
-        assert_eq!(
-            body_bytes,
-            res,
-            "test case {}, left: {}, right: {}",
-            self.name.unwrap_or_default(),
-            std::str::from_utf8(&body_bytes).unwrap(),
-            std::str::from_utf8(&res).unwrap()
-        );
-
-        Ok(())
+        Ok(TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: body_to_bytes(body)?.into(),
+        })
     }
 }
@@ -1,7 +1,9 @@
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, parse_quote, Data, DeriveInput, GenericParam, Generics};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Field, GenericParam, Generics, Ident, Index,
+};
 
 #[proc_macro_derive(FromStored)]
 pub fn my_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -69,3 +71,74 @@ fn call_from_param(data: &Data) -> TokenStream {
         Data::Enum(_) | Data::Union(_) => unimplemented!(),
     }
 }
+
+/// Implements `core::request::FromRef` of the struct for types of its fields,
+/// so handlers can take `State<Field>` instead of the whole state.
+/// Fields marked with `#[from_ref(skip)]` are left out.
+#[proc_macro_derive(FromRef, attributes(from_ref))]
+pub fn derive_from_ref(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match from_ref_impls(&input) {
+        Ok(impls) => proc_macro::TokenStream::from(impls),
+        Err(err) => proc_macro::TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn from_ref_impls(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(_) | Data::Union(_) => {
+            return Err(syn::Error::new(
+                name.span(),
+                "FromRef can be derived only for structs",
+            ))
+        }
+    };
+
+    let mut impls = vec![];
+    for (index, field) in fields.iter().enumerate() {
+        if is_skipped(field)? {
+            continue;
+        }
+
+        let ty = &field.ty;
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = Index::from(index);
+                quote!(#index)
+            }
+        };
+
+        impls.push(quote_spanned!(field.span() =>
+            impl #impl_generics core::request::FromRef<#name #ty_generics> for #ty #where_clause {
+                fn from_ref(state: &#name #ty_generics) -> Self {
+                    ::std::clone::Clone::clone(&state.#member)
+                }
+            }
+        ));
+    }
+
+    Ok(quote!(#(#impls)*))
+}
+
+/// Checks if field is marked with `#[from_ref(skip)]`.
+fn is_skipped(field: &Field) -> syn::Result<bool> {
+    let mut skipped = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("from_ref"))
+    {
+        let arg: Ident = attr.parse_args()?;
+        if arg != "skip" {
+            return Err(syn::Error::new(arg.span(), "expected `skip`"));
+        }
+        skipped = true;
+    }
+    Ok(skipped)
+}